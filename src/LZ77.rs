@@ -1,20 +1,76 @@
 #![allow(unused)]
-use std::collections::LinkedList;
 
-use crate::bit_io::Reader;
+use std::{error, fmt};
 
 pub struct Codec {
     window_size: usize,
     look_ahead_size: usize,
     min_match_size: usize,
+    // match-finder effort, see the setters below
+    max_chain: usize,
+    nice_length: usize,
+    good_length: usize,
+    lazy_length: usize,
+    parsing: Parsing,
+    finder_kind: FinderKind,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// How `Codec` picks between the match at the current position and the
+/// ones right after it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Parsing {
+    /// always take the longest match at the current position
+    #[default]
+    Greedy,
+    /// Check whether one of the next `depth` positions has a match long
+    /// enough to pay for the literals emitted until there, and start there
+    /// if so. zlib's lazy matching is a depth of 1.
+    Lazy { depth: usize },
+}
+
+/// How `Codec` looks for earlier occurrences of the upcoming bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FinderKind {
+    /// zlib-style chains of positions with the same hash, see `HashChain`
+    #[default]
+    HashChain,
+    /// LZMA bt4-style binary trees of the suffixes in the window, finds the
+    /// nearest match of every length in far fewer steps on repetitive data
+    BinaryTree,
+    /// Suffix array with LCPs over the whole input, built up front
+    SuffixArray,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Value {
     Literal(u8),
     BackRef { len: usize, distance: usize },
 }
 
+/// Why `Codec::decode` rejected the tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// a back-reference of distance 0, or one reaching before the output so
+    /// far or further back than the window
+    InvalidDistance { distance: usize, available: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::InvalidDistance {
+                distance,
+                available,
+            } => write!(
+                f,
+                "invalid back-reference distance {distance}, {available} bytes available"
+            ),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
 impl Codec {
     pub fn new(window_size: usize, look_ahead_size: usize, min_match_size: usize) -> Self {
         assert!(window_size > 0 && look_ahead_size > 0);
@@ -22,90 +78,550 @@ impl Codec {
             window_size,
             look_ahead_size,
             min_match_size,
+            max_chain: usize::MAX,
+            nice_length: look_ahead_size,
+            good_length: look_ahead_size,
+            lazy_length: look_ahead_size,
+            parsing: Parsing::default(),
+            finder_kind: FinderKind::default(),
         }
     }
 
+    /// Give up after checking this many earlier positions that could start a match
+    pub fn max_chain(mut self, max_chain: usize) -> Self {
+        assert!(max_chain > 0);
+        self.max_chain = max_chain;
+        self
+    }
+
+    /// Stop searching as soon as a match this long is found
+    pub fn nice_length(mut self, nice_length: usize) -> Self {
+        self.nice_length = nice_length;
+        self
+    }
+
+    /// With lazy matching, only search a quarter of the chain for a better
+    /// match when we already have one this long
+    pub fn good_length(mut self, good_length: usize) -> Self {
+        self.good_length = good_length;
+        self
+    }
+
+    /// With lazy parsing, only look ahead for a better match when the
+    /// current one is shorter than this
+    pub fn lazy_length(mut self, lazy_length: usize) -> Self {
+        self.lazy_length = lazy_length;
+        self
+    }
+
+    pub fn parsing(mut self, parsing: Parsing) -> Self {
+        self.parsing = parsing;
+        self
+    }
+
+    pub fn finder_kind(mut self, finder_kind: FinderKind) -> Self {
+        self.finder_kind = finder_kind;
+        self
+    }
+
     pub fn encode(&self, input: &[u8]) -> Vec<Value> {
-        let mut search_window: LinkedList<u8> = LinkedList::new();
-        let mut look_ahead_window: LinkedList<u8> = LinkedList::new();
-        let mut reader = Reader::new(input);
-        for _ in 0..self.look_ahead_size {
-            if let Some(val) = reader.read_u8() {
-                look_ahead_window.push_back(val);
-            } else {
-                break;
+        self.encode_from(input, 0)
+    }
+
+    /// Encode `input` as if it followed `dictionary`, so back references may
+    /// point into the last `window_size` bytes of the dictionary
+    pub fn encode_with_dictionary(&self, dictionary: &[u8], input: &[u8]) -> Vec<Value> {
+        let dictionary = &dictionary[dictionary.len().saturating_sub(self.window_size)..];
+        self.encode_from(&[dictionary, input].concat(), dictionary.len())
+    }
+
+    /// Encode `input[start..]`, back references may reach into the history
+    /// before `start`
+    pub fn encode_from(&self, input: &[u8], start: usize) -> Vec<Value> {
+        let mut res = vec![];
+        let mut finder = Finder::new(self, input, start);
+        let mut pos = start;
+        let mut current = finder.longest(pos, self.max_chain);
+        while pos < input.len() {
+            match current {
+                Some((len, distance)) => {
+                    if let Some((step, next)) = self.deferred_match(&mut finder, input, pos, len) {
+                        // defer: emit literals up to the longer match and take it
+                        res.extend(
+                            input[pos..pos + step]
+                                .iter()
+                                .map(|&byte| Value::Literal(byte)),
+                        );
+                        pos += step;
+                        current = Some(next);
+                        continue;
+                    }
+                    res.push(Value::BackRef { len, distance });
+                    pos += len;
+                }
+                None => {
+                    res.push(Value::Literal(input[pos]));
+                    pos += 1;
+                }
             }
+            current = finder.longest(pos, self.max_chain);
         }
-        let mut res = vec![];
-        while !reader.is_empty() || !look_ahead_window.is_empty() {
-            let cur_val = *look_ahead_window.front().unwrap();
-            let mut match_len = 0;
-            if let Some((index, len)) = self.max_match(&search_window, &look_ahead_window) {
-                let distance = search_window.len() - index;
-                match_len = len;
-                res.push(Value::BackRef { len, distance });
-            } else {
-                res.push(Value::Literal(cur_val));
+        res
+    }
+
+    /// With lazy parsing, the first of the next positions whose match is
+    /// longer than the `len` at `pos` by more than the literals it takes to
+    /// get there, as (step, (len, distance))
+    fn deferred_match(
+        &self,
+        finder: &mut Finder,
+        input: &[u8],
+        pos: usize,
+        len: usize,
+    ) -> Option<(usize, (usize, usize))> {
+        let Parsing::Lazy { depth } = self.parsing else {
+            return None;
+        };
+        if len >= self.lazy_length {
+            return None;
+        }
+        let chain = if len >= self.good_length {
+            (self.max_chain / 4).max(1)
+        } else {
+            self.max_chain
+        };
+        // no further than where the current match ends, the finder can't go back
+        (1..=depth.min(len))
+            .take_while(|step| pos + step < input.len())
+            .find_map(|step| match finder.longest(pos + step, chain) {
+                Some(next) if next.0 >= len + step => Some((step, next)),
+                _ => None,
+            })
+    }
+
+    pub fn decode(&self, tokens: &[Value]) -> Result<Vec<u8>, DecodeError> {
+        self.decode_with_dictionary(&[], tokens)
+    }
+
+    /// Inverse of `encode_with_dictionary`, returns the decoded bytes
+    /// without the dictionary
+    ///
+    /// A back reference may be longer than its distance, then it copies
+    /// bytes it has just written, e.g. a run of one repeated byte.
+    pub fn decode_with_dictionary(
+        &self,
+        dictionary: &[u8],
+        tokens: &[Value],
+    ) -> Result<Vec<u8>, DecodeError> {
+        let dictionary = &dictionary[dictionary.len().saturating_sub(self.window_size)..];
+        let mut output = dictionary.to_vec();
+        for &token in tokens {
+            match token {
+                Value::Literal(byte) => output.push(byte),
+                Value::BackRef { len, distance } => {
+                    let available = output.len().min(self.window_size);
+                    if distance == 0 || distance > available {
+                        return Err(DecodeError::InvalidDistance {
+                            distance,
+                            available,
+                        });
+                    }
+                    // byte by byte, the source may overlap what we are writing
+                    let start = output.len() - distance;
+                    for i in 0..len {
+                        output.push(output[start + i]);
+                    }
+                }
             }
+        }
+        output.drain(..dictionary.len());
+        Ok(output)
+    }
+
+    /// The configured match finder over `input`, see `Finder::candidates`
+    pub fn finder<'a>(&'a self, input: &'a [u8]) -> Finder<'a> {
+        Finder::new(self, input, 0)
+    }
+}
+
+/// A match finder over one input, as chosen by `Codec::finder_kind`.
+///
+/// The hash chain and binary tree take positions in as the search moves
+/// forward, so positions have to be queried in non-decreasing order.
+pub enum Finder<'a> {
+    HashChain(HashChain<'a>),
+    BinaryTree(BinaryTree<'a>),
+    SuffixArray(SuffixArray<'a>),
+}
+
+impl<'a> Finder<'a> {
+    fn new(codec: &'a Codec, input: &'a [u8], start: usize) -> Self {
+        match codec.finder_kind {
+            FinderKind::HashChain => Finder::HashChain(HashChain::new(codec, input, start)),
+            FinderKind::BinaryTree => Finder::BinaryTree(BinaryTree::new(codec, input, start)),
+            FinderKind::SuffixArray => Finder::SuffixArray(SuffixArray::new(codec, input)),
+        }
+    }
+
+    /// Every match at `pos` that is longer than the ones closer to it, as
+    /// (len, distance) pairs. The closest distance for a given length is the
+    /// first pair at least that long, which is what an optimal parser needs.
+    pub fn candidates(&mut self, pos: usize) -> Vec<(usize, usize)> {
+        let max_chain = match self {
+            Finder::HashChain(finder) => finder.codec.max_chain,
+            Finder::BinaryTree(finder) => finder.codec.max_chain,
+            Finder::SuffixArray(finder) => finder.codec.max_chain,
+        };
+        self.find(pos, max_chain)
+    }
+
+    /// Longest (len, distance) match for the data at `pos`
+    fn longest(&mut self, pos: usize, max_chain: usize) -> Option<(usize, usize)> {
+        self.find(pos, max_chain).pop()
+    }
+
+    fn find(&mut self, pos: usize, max_chain: usize) -> Vec<(usize, usize)> {
+        match self {
+            Finder::HashChain(finder) => finder.find(pos, max_chain),
+            Finder::BinaryTree(finder) => finder.find(pos, max_chain),
+            Finder::SuffixArray(finder) => finder.find(pos, max_chain),
+        }
+    }
+}
+
+/// Bits of the hash of the first bytes at a position
+const HASH_BITS: usize = 15;
+const HASH_SHIFT: usize = 5;
+/// End of a hash chain or an empty subtree
+const NIL: usize = usize::MAX;
+
+fn hash(input: &[u8], pos: usize, len: usize) -> usize {
+    input[pos..pos + len].iter().fold(0, |hash, &byte| {
+        ((hash << HASH_SHIFT) ^ byte as usize) & ((1 << HASH_BITS) - 1)
+    })
+}
+
+/// Number of equal bytes at the start of `a` and `b`, at most `limit`
+fn common_len(input: &[u8], a: usize, b: usize, from: usize, limit: usize) -> usize {
+    from + input[a + from..]
+        .iter()
+        .zip(&input[b + from..b + limit])
+        .take_while(|(n1, n2)| n1 == n2)
+        .count()
+}
 
-            for _ in 0..1.max(match_len) {
-                if search_window.len() == self.window_size {
-                    search_window.pop_front();
+/// zlib-style match finder: `head` holds the latest position for each hash
+/// of the first `hash_len` bytes there, `prev` links every position in the
+/// window to the previous one with the same hash. Only positions that can
+/// start a match are visited, instead of every offset in the window.
+pub struct HashChain<'a> {
+    codec: &'a Codec,
+    input: &'a [u8],
+    // a match of at least min_match_size bytes shares this many with its source
+    hash_len: usize,
+    head: Vec<usize>,
+    // indexed by position modulo the window size
+    prev: Vec<usize>,
+    // positions before this one are in the chains
+    inserted: usize,
+}
+
+impl<'a> HashChain<'a> {
+    fn new(codec: &'a Codec, input: &'a [u8], start: usize) -> Self {
+        Self {
+            codec,
+            input,
+            hash_len: codec.min_match_size.clamp(1, 3),
+            head: vec![NIL; 1 << HASH_BITS],
+            prev: vec![NIL; codec.window_size],
+            inserted: start.saturating_sub(codec.window_size),
+        }
+    }
+
+    /// Add the positions up to `pos` to the chains
+    fn insert_until(&mut self, pos: usize) {
+        assert!(
+            pos >= self.inserted,
+            "match finder positions must not go back"
+        );
+        let end = pos.min((self.input.len() + 1).saturating_sub(self.hash_len));
+        for position in self.inserted..end {
+            let hash = hash(self.input, position, self.hash_len);
+            self.prev[position % self.codec.window_size] = self.head[hash];
+            self.head[hash] = position;
+        }
+        self.inserted = pos;
+    }
+
+    /// Walk the chain from the nearest candidate backwards, keeping each one
+    /// that beats the longest so far. The match may run past `pos`, which is
+    /// how a short pattern repeats itself.
+    fn find(&mut self, pos: usize, max_chain: usize) -> Vec<(usize, usize)> {
+        self.insert_until(pos);
+        let codec = self.codec;
+        let mut res = vec![];
+        let max_len = codec.look_ahead_size.min(self.input.len() - pos);
+        if max_len < codec.min_match_size.max(1) {
+            return res;
+        }
+        let mut chain = max_chain;
+        let mut best_len = codec.min_match_size.max(1) - 1;
+        let mut candidate = self.head[hash(self.input, pos, self.hash_len)];
+        while candidate != NIL && pos - candidate <= codec.window_size {
+            let len = self.input[candidate..]
+                .iter()
+                .zip(&self.input[pos..pos + max_len])
+                .take_while(|(n1, n2)| n1 == n2)
+                .count();
+            // a shorter one is a hash collision, which costs no chain
+            if len >= self.hash_len {
+                if len > best_len {
+                    best_len = len;
+                    res.push((len, pos - candidate));
+                    if len >= codec.nice_length.min(max_len) {
+                        break;
+                    }
                 }
-                let cur_val = look_ahead_window.pop_front().unwrap();
-                search_window.push_back(cur_val);
-                if let Some(next_val) = reader.read_u8() {
-                    look_ahead_window.push_back(next_val);
+                chain -= 1;
+                if chain == 0 {
+                    break;
                 }
             }
-            // if match_len > 0 {
-            //     println!("look_ahead_window: {look_ahead_window:?}");
-            //     println!("search_window: {search_window:?}");
-            // }
+            candidate = self.prev[candidate % codec.window_size];
         }
         res
     }
+}
 
-    fn max_match(&self, list1: &LinkedList<u8>, list2: &LinkedList<u8>) -> Option<(usize, usize)> {
-        let mut max_match_len = 0;
-        let mut match_index = 0;
-        for skip in 0..list1.len() {
-            for (i, (n1, n2)) in list1.iter().skip(skip).zip(list2).enumerate() {
-                if n1 != n2 {
-                    if i >= max_match_len && i >= self.min_match_size {
-                        max_match_len = i;
-                        match_index = skip;
-                    }
-                    break;
+/// LZMA bt4-style match finder: every hash bucket is a binary search tree
+/// of the suffixes starting in the window, ordered by their bytes and with
+/// newer positions above older ones. Inserting the current position walks
+/// from the root down to where it belongs, and the nodes on that path are
+/// exactly the nearest match of every length. The walk also splits the tree
+/// into the new position's subtrees, so it becomes the root.
+pub struct BinaryTree<'a> {
+    codec: &'a Codec,
+    input: &'a [u8],
+    hash_len: usize,
+    head: Vec<usize>,
+    // (smaller, larger) children, indexed by position modulo the window
+    // size + 1 so a node a full window back is still there
+    children: Vec<[usize; 2]>,
+    // positions before this one are in the trees
+    inserted: usize,
+    // what the last position inserted found, it may be asked for twice
+    last: (usize, Vec<(usize, usize)>),
+}
+
+impl<'a> BinaryTree<'a> {
+    fn new(codec: &'a Codec, input: &'a [u8], start: usize) -> Self {
+        Self {
+            codec,
+            input,
+            hash_len: codec.min_match_size.clamp(1, 3),
+            head: vec![NIL; 1 << HASH_BITS],
+            children: vec![[NIL; 2]; codec.window_size + 1],
+            inserted: start.saturating_sub(codec.window_size),
+            last: (NIL, vec![]),
+        }
+    }
+
+    fn find(&mut self, pos: usize, max_chain: usize) -> Vec<(usize, usize)> {
+        if pos == self.last.0 {
+            return self.last.1.clone();
+        }
+        assert!(
+            pos >= self.inserted,
+            "match finder positions must not go back"
+        );
+        // skipped positions have to be in the trees all the same
+        for position in self.inserted..pos {
+            self.insert(position, max_chain);
+        }
+        let res = self.insert(pos, max_chain);
+        self.inserted = pos + 1;
+        self.last = (pos, res.clone());
+        res
+    }
+
+    /// Make `pos` the root of its tree, returning the matches on the way
+    fn insert(&mut self, pos: usize, max_chain: usize) -> Vec<(usize, usize)> {
+        let codec = self.codec;
+        let mut res = vec![];
+        if self.input.len() - pos < self.hash_len {
+            return res;
+        }
+        let max_len = codec.look_ahead_size.min(self.input.len() - pos);
+        // comparing further than this is not worth it, the node is replaced
+        let limit = codec.nice_length.clamp(1, max_len);
+        let size = self.children.len();
+        let hash = hash(self.input, pos, self.hash_len);
+        let mut candidate = self.head[hash];
+        self.head[hash] = pos;
+
+        // where the next node smaller and larger than `pos` gets linked
+        let mut smaller = (pos % size, 0);
+        let mut larger = (pos % size, 1);
+        // every node below shares this much with `pos`, being between nodes
+        // that do on both sides
+        let (mut smaller_len, mut larger_len) = (0, 0);
+        let mut chain = max_chain;
+        let mut best_len = codec.min_match_size.max(1) - 1;
+        loop {
+            if candidate == NIL || pos - candidate > codec.window_size || chain == 0 {
+                self.children[smaller.0][smaller.1] = NIL;
+                self.children[larger.0][larger.1] = NIL;
+                return res;
+            }
+            chain -= 1;
+            let node = candidate % size;
+            let len = common_len(
+                self.input,
+                candidate,
+                pos,
+                smaller_len.min(larger_len),
+                limit,
+            );
+            if len == limit {
+                // same as `pos` as far as we look: `pos` takes its place
+                let len = common_len(self.input, candidate, pos, len, max_len);
+                if len > best_len {
+                    res.push((len, pos - candidate));
                 }
-                if i == list1.len() - 1 && i < list2.len() - 1 {
-                    // compare prefix and suffix
-                    let mut self_max_match_len = 0;
-                    for (ii, (n1, n2)) in list2.iter().skip(i + 1).zip(list2).enumerate() {
-                        if n1 != n2 {
-                            if ii >= self_max_match_len && ii + i >= self.min_match_size {
-                                self_max_match_len = ii;
-                            }
-                            break;
-                        }
-                    }
-                    max_match_len = i + 1 + self_max_match_len;
-                    match_index = skip;
+                self.children[smaller.0][smaller.1] = self.children[node][0];
+                self.children[larger.0][larger.1] = self.children[node][1];
+                return res;
+            }
+            if len > best_len {
+                best_len = len;
+                res.push((len, pos - candidate));
+            }
+            if self.input[candidate + len] < self.input[pos + len] {
+                self.children[smaller.0][smaller.1] = candidate;
+                smaller = (node, 1);
+                smaller_len = len;
+                candidate = self.children[node][1];
+            } else {
+                self.children[larger.0][larger.1] = candidate;
+                larger = (node, 0);
+                larger_len = len;
+                candidate = self.children[node][0];
+            }
+        }
+    }
+}
+
+/// Suffix array match finder for compressing a whole buffer at once. The
+/// suffixes sharing the longest prefix with the one at `pos` are its
+/// neighbours in sorted order, so the search walks outwards from there,
+/// always to the side with the longer common prefix, until it drops below
+/// the minimum match length. `max_chain` bounds the suffixes visited,
+/// including the ones that can't be used because they come after `pos` or
+/// lie outside the window; `nice_length` doesn't apply.
+pub struct SuffixArray<'a> {
+    codec: &'a Codec,
+    input: &'a [u8],
+    // the suffixes of `input` in sorted order, and each one's index there
+    suffixes: Vec<usize>,
+    rank: Vec<usize>,
+    // lcp[i] is the common prefix length of suffixes[i - 1] and suffixes[i]
+    lcp: Vec<usize>,
+}
+
+impl<'a> SuffixArray<'a> {
+    fn new(codec: &'a Codec, input: &'a [u8]) -> Self {
+        let n = input.len();
+        // prefix doubling: sort by the first k bytes, then by 2k
+        let mut suffixes: Vec<usize> = (0..n).collect();
+        let mut rank: Vec<usize> = input.iter().map(|&byte| byte as usize).collect();
+        let mut next = vec![0; n];
+        let mut k = 1;
+        let mut sorted = n == 0;
+        while !sorted {
+            let key = |i: usize| (rank[i], rank.get(i + k).map_or(0, |&r| r + 1));
+            suffixes.sort_unstable_by_key(|&i| key(i));
+            next[suffixes[0]] = 0;
+            for i in 1..n {
+                next[suffixes[i]] =
+                    next[suffixes[i - 1]] + usize::from(key(suffixes[i - 1]) != key(suffixes[i]));
+            }
+            std::mem::swap(&mut rank, &mut next);
+            sorted = rank[suffixes[n - 1]] == n - 1;
+            k *= 2;
+        }
+
+        // Kasai: the next suffix in text order shares at least one byte less
+        let mut lcp = vec![0; n];
+        let mut len: usize = 0;
+        for i in 0..n {
+            if rank[i] == 0 {
+                len = 0;
+                continue;
+            }
+            let j = suffixes[rank[i] - 1];
+            len = common_len(input, i, j, len, n - i.max(j));
+            lcp[rank[i]] = len;
+            len = len.saturating_sub(1);
+        }
+        Self {
+            codec,
+            input,
+            suffixes,
+            rank,
+            lcp,
+        }
+    }
+
+    fn find(&mut self, pos: usize, max_chain: usize) -> Vec<(usize, usize)> {
+        let codec = self.codec;
+        let max_len = codec.look_ahead_size.min(self.input.len() - pos);
+        let min_len = codec.min_match_size.max(1);
+        let mut found = vec![];
+        if max_len < min_len {
+            return found;
+        }
+        let rank = self.rank[pos];
+        // the next suffix on either side and the prefix it shares with `pos`
+        let (mut up, mut up_len) = (rank, usize::MAX);
+        let (mut down, mut down_len) = (rank, usize::MAX);
+        let mut chain = max_chain;
+        while chain > 0 {
+            let up_next = if up > 0 { up_len.min(self.lcp[up]) } else { 0 };
+            let down_next = if down + 1 < self.suffixes.len() {
+                down_len.min(self.lcp[down + 1])
+            } else {
+                0
+            };
+            let candidate = if up_next >= down_next {
+                if up_next.min(max_len) < min_len {
                     break;
                 }
-                if i == list2.len() - 1 {
-                    if i + 1 >= self.min_match_size {
-                        max_match_len = i + 1;
-                        match_index = skip;
-                    }
+                up -= 1;
+                up_len = up_next;
+                self.suffixes[up]
+            } else {
+                if down_next.min(max_len) < min_len {
+                    break;
                 }
+                down += 1;
+                down_len = down_next;
+                self.suffixes[down]
+            };
+            // later suffixes and ones out of the window cost as much to step over
+            chain -= 1;
+            if candidate < pos && pos - candidate <= codec.window_size {
+                found.push((up_next.max(down_next).min(max_len), pos - candidate));
             }
         }
-        if max_match_len == 0 {
-            None
-        } else {
-            Some((match_index, max_match_len))
+        // keep the nearest one of every length
+        found.sort_unstable_by_key(|&(_, distance)| distance);
+        let mut res: Vec<(usize, usize)> = vec![];
+        for (len, distance) in found {
+            if res.last().is_none_or(|&(best_len, _)| len > best_len) {
+                res.push((len, distance));
+            }
         }
+        res
     }
 }
 
@@ -140,6 +656,283 @@ mod tests {
         println!("{res:?}");
         res.iter().zip(expected.iter()).for_each(|(v1, v2)| {
             assert_eq!(v1, v2);
-        })
+        });
+        assert_eq!(codec.decode(&res).unwrap(), input);
+    }
+
+    #[test]
+    fn test_lz77_decode() {
+        let codec = Codec::new(4, 100, 2);
+        // "SALSA" copies from earlier output, the run of "A"s reads its own output
+        let tokens = [
+            Value::Literal(b'S'),
+            Value::Literal(b'A'),
+            Value::Literal(b'L'),
+            Value::BackRef {
+                len: 2,
+                distance: 3,
+            },
+            Value::BackRef {
+                len: 5,
+                distance: 1,
+            },
+        ];
+        assert_eq!(codec.decode(&tokens).unwrap(), b"SALSAAAAAA");
+        assert_eq!(
+            codec
+                .decode_with_dictionary(b"xxSAL", &tokens[3..4])
+                .unwrap(),
+            b"SA"
+        );
+
+        for (output, distance, available) in [(&b"ab"[..], 3, 2), (b"abcdef", 5, 4), (b"a", 0, 1)] {
+            let tokens: Vec<_> = output
+                .iter()
+                .map(|&byte| Value::Literal(byte))
+                .chain([Value::BackRef { len: 1, distance }])
+                .collect();
+            assert_eq!(
+                codec.decode(&tokens),
+                Err(DecodeError::InvalidDistance {
+                    distance,
+                    available
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_lz77_round_trip() {
+        // decode(encode(x)) == x over random inputs and codec settings
+        let mut seed = 12345u64;
+        let mut next = move |n: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        for _ in 0..200 {
+            let alphabet = 1 + next(8) as u8;
+            let len = next(600) as usize;
+            let input: Vec<u8> = (0..len)
+                .map(|_| b'a' + next(alphabet as u64) as u8)
+                .collect();
+            let split = next(len as u64 + 1) as usize;
+            let codec = Codec::new(
+                1 + next(64) as usize,
+                1 + next(20) as usize,
+                next(5) as usize,
+            )
+            .max_chain(1 + next(16) as usize)
+            .lazy_length(next(8) as usize)
+            .parsing(Parsing::Lazy {
+                depth: next(4) as usize,
+            })
+            .finder_kind(
+                [
+                    FinderKind::HashChain,
+                    FinderKind::BinaryTree,
+                    FinderKind::SuffixArray,
+                ][next(3) as usize],
+            );
+
+            let tokens = codec.encode(&input);
+            assert_eq!(codec.decode(&tokens).unwrap(), input);
+            let (dictionary, rest) = input.split_at(split);
+            let tokens = codec.encode_with_dictionary(dictionary, rest);
+            assert_eq!(
+                codec.decode_with_dictionary(dictionary, &tokens).unwrap(),
+                rest
+            );
+        }
+    }
+
+    #[test]
+    fn test_lz77_hash_chain() {
+        // every offset in the window with the same first byte, the way
+        // matches were searched before
+        fn scan(codec: &Codec, input: &[u8], pos: usize, max_chain: usize) -> usize {
+            let max_len = codec.look_ahead_size.min(input.len() - pos);
+            let mut chain = max_chain;
+            let mut best_len = 0;
+            for candidate in (pos.saturating_sub(codec.window_size)..pos).rev() {
+                if input[candidate] != input[pos] {
+                    continue;
+                }
+                let len = input[candidate..]
+                    .iter()
+                    .zip(&input[pos..pos + max_len])
+                    .take_while(|(n1, n2)| n1 == n2)
+                    .count();
+                best_len = best_len.max(len);
+                chain -= 1;
+                if chain == 0 {
+                    break;
+                }
+            }
+            best_len
+        }
+
+        let input: Vec<u8> = b"A SALAD; A SALSA, a salad and salsa; A SALAD SALSA"
+            .iter()
+            .cycle()
+            .take(400)
+            .enumerate()
+            .map(|(i, &byte)| if i % 37 == 0 { b'#' } else { byte })
+            .collect();
+        for (min_match, max_chain) in [(1, 1000), (2, 1000), (3, 1000), (4, 1000), (3, 2), (2, 1)] {
+            let codec = Codec::new(50, 20, min_match).max_chain(max_chain);
+            let mut finder = codec.finder(&input);
+            for pos in 0..input.len() {
+                let len = finder.candidates(pos).pop().map_or(0, |(len, _)| len);
+                let expected = scan(&codec, &input, pos, max_chain);
+                if max_chain == 1000 {
+                    assert_eq!(len, if expected < min_match { 0 } else { expected });
+                } else {
+                    assert!(len >= expected || expected < min_match);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lz77_match_finders() {
+        // searching without limits, every finder sees the same candidates
+        let text = b"abracadabra, abracadabra! cadabra abra ca da bra; aaaaaaaaaaaabababab";
+        let zeros = [0; 300];
+        for input in [&text[..], &text.repeat(5), &zeros] {
+            for (window, look_ahead, min_match) in [(40, 10, 3), (1000, 1000, 2), (7, 300, 1)] {
+                let codecs = [
+                    FinderKind::HashChain,
+                    FinderKind::BinaryTree,
+                    FinderKind::SuffixArray,
+                ]
+                .map(|finder| Codec::new(window, look_ahead, min_match).finder_kind(finder));
+                let mut finders = codecs.each_ref().map(|codec| codec.finder(input));
+                for pos in 0..input.len() {
+                    let expected = finders[0].candidates(pos);
+                    assert_eq!(finders[1].candidates(pos), expected, "bt at {pos}");
+                    assert_eq!(finders[2].candidates(pos), expected, "sa at {pos}");
+                }
+            }
+        }
+
+        // skipping positions still keeps them in the tree
+        let codec = Codec::new(100, 100, 3).finder_kind(FinderKind::BinaryTree);
+        let input = b"abcdefgh abcdefgh cdefgh";
+        let mut finder = codec.finder(input);
+        assert_eq!(finder.candidates(0), []);
+        assert_eq!(finder.candidates(18), [(6, 7)]);
+    }
+
+    #[test]
+    fn test_lz77_dictionary() {
+        let codec = Codec::new(100, 100, 3);
+        let res = codec.encode_with_dictionary(b"{\"name\": ", b"{\"name\": 1}");
+        assert_eq!(
+            res,
+            [
+                Value::BackRef {
+                    len: 9,
+                    distance: 9
+                },
+                Value::Literal(b'1'),
+                Value::Literal(b'}')
+            ]
+        );
+        // only the last window_size bytes of the dictionary are reachable
+        let res = Codec::new(4, 100, 3).encode_with_dictionary(b"abcdefgh", b"abc");
+        assert_eq!(res.len(), 3);
+    }
+
+    #[test]
+    fn test_lz77_effort() {
+        // the match at 'a' is shorter than the one starting at the next 'b'
+        let input = b"abcd_bcdefgh_abcdefgh";
+        let greedy = Codec::new(100, 100, 4).encode(input);
+        assert_eq!(
+            greedy[greedy.len() - 2..],
+            [
+                Value::BackRef {
+                    len: 4,
+                    distance: 13
+                },
+                Value::BackRef {
+                    len: 4,
+                    distance: 9
+                }
+            ]
+        );
+        let lazy = Codec::new(100, 100, 4)
+            .parsing(Parsing::Lazy { depth: 1 })
+            .encode(input);
+        assert_eq!(
+            lazy[lazy.len() - 2..],
+            [
+                Value::Literal(b'a'),
+                Value::BackRef {
+                    len: 7,
+                    distance: 9
+                }
+            ]
+        );
+
+        // only two positions on is a match long enough to pay for two literals
+        let input = b"abcQ_cdefghij_abcdefghij";
+        let depth = |depth| {
+            let tokens = Codec::new(100, 100, 3)
+                .parsing(Parsing::Lazy { depth })
+                .encode(input);
+            tokens[14..].to_vec()
+        };
+        assert_eq!(
+            depth(1),
+            [
+                Value::BackRef {
+                    len: 3,
+                    distance: 14
+                },
+                Value::BackRef {
+                    len: 7,
+                    distance: 11
+                }
+            ]
+        );
+        assert_eq!(
+            depth(2),
+            [
+                Value::Literal(b'a'),
+                Value::Literal(b'b'),
+                Value::BackRef {
+                    len: 8,
+                    distance: 11
+                }
+            ]
+        );
+
+        // the nearest candidate is shorter, a chain of one never sees the farther one
+        let input = b"abcdef1abcx2abcdef";
+        let full = Codec::new(100, 100, 3).encode(input);
+        assert_eq!(
+            full.last(),
+            Some(&Value::BackRef {
+                len: 6,
+                distance: 12
+            })
+        );
+        let short = Codec::new(100, 100, 3).max_chain(1).encode(input);
+        assert_eq!(
+            short[short.len() - 2..],
+            [
+                Value::BackRef {
+                    len: 3,
+                    distance: 5
+                },
+                Value::BackRef {
+                    len: 3,
+                    distance: 12
+                }
+            ]
+        );
     }
 }
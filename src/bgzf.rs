@@ -0,0 +1,306 @@
+#![allow(unused)]
+
+use std::{
+    collections::LinkedList,
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+};
+
+use crate::{
+    bit_io::BitIO,
+    deflate::{
+        deflate_raw_level, ExtraSubfield, GZip, GZipBuilder, GZipFooter, DEFAULT_LEVEL, MAX_LEVEL,
+    },
+    inflate::InflateError,
+};
+
+// refer to https://samtools.github.io/hts-specs/SAMv1.pdf S4.1
+const BLOCK_SIZE_ID: [u8; 2] = *b"BC";
+// input per block, small enough that even stored the block fits in 64K
+const MAX_BLOCK_DATA: usize = 0xff00;
+const MAX_BLOCK_SIZE: usize = 1 << 16;
+const OS_UNKNOWN: u8 = 255;
+// size of a gzip header up to and including XLEN
+const FIXED_HEADER_SIZE: usize = 12;
+/// The empty block that marks the end of a BGZF file
+pub const EOF_MARKER: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Combine the file offset of a block with an offset into its data
+pub fn virtual_offset(block_offset: u64, data_offset: u16) -> u64 {
+    (block_offset << 16) | data_offset as u64
+}
+
+/// Compress one block of at most `MAX_BLOCK_DATA` bytes as a gzip member
+/// whose BC subfield holds the member size minus 1
+fn write_block(data: &[u8], level: u8) -> Vec<u8> {
+    assert!(data.len() <= MAX_BLOCK_DATA);
+    // adaptive blocks are never larger than stored ones, so this fits
+    let compressed = deflate_raw_level(data, level).as_vec();
+    let block_size = FIXED_HEADER_SIZE + 6 + compressed.len() + 8;
+    assert!(block_size <= MAX_BLOCK_SIZE);
+
+    let mut bit_io = BitIO::new(LinkedList::new());
+    GZipBuilder::new()
+        .modification_time(0)
+        .os(OS_UNKNOWN)
+        .extra_subfield(BLOCK_SIZE_ID, &((block_size - 1) as u16).to_le_bytes())
+        .expect("the BSIZE subfield fits into FEXTRA")
+        .header()
+        .write(&mut bit_io);
+    for byte in compressed {
+        bit_io.write_byte_align(byte);
+    }
+    GZipFooter {
+        crc32: crc32fast::hash(data),
+        input_size: data.len() as u32,
+    }
+    .write(&mut bit_io);
+    bit_io.as_vec()
+}
+
+/// Write BGZF: gzip members of at most 64K each, which plain gzip tools
+/// read as one file but which can be entered at any member
+///
+/// `finish`, or dropping the writer, appends the EOF marker block.
+pub struct BgzfWriter<W: Write> {
+    inner: Option<W>,
+    level: u8,
+    buffer: Vec<u8>,
+    // file offset of the block being filled
+    block_offset: u64,
+}
+
+impl<W: Write> BgzfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner: Some(inner),
+            level: DEFAULT_LEVEL,
+            buffer: Vec::with_capacity(MAX_BLOCK_DATA),
+            block_offset: 0,
+        }
+    }
+
+    /// Compression level of the blocks, from 0 to 9
+    pub fn level(mut self, level: u8) -> Self {
+        assert!(level <= MAX_LEVEL, "compression level must be 0 to 9");
+        self.level = level;
+        self
+    }
+
+    /// Virtual offset of the next byte written, save it to seek back to
+    /// this point with `BgzfReader::seek_virtual`
+    pub fn virtual_offset(&self) -> u64 {
+        virtual_offset(self.block_offset, self.buffer.len() as u16)
+    }
+
+    /// Write out the pending data as a block even if it is not full yet,
+    /// e.g. so a record starts at the beginning of a block
+    pub fn flush_block(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let block = write_block(&self.buffer, self.level);
+        self.inner.as_mut().unwrap().write_all(&block)?;
+        self.block_offset += block.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Write the last block and the EOF marker, then hand back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        self.flush_block()?;
+        self.inner.as_mut().unwrap().write_all(&EOF_MARKER)
+    }
+}
+
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(MAX_BLOCK_DATA - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        if self.buffer.len() == MAX_BLOCK_DATA {
+            self.flush_block()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_block()?;
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for BgzfWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// Read BGZF a block at a time, with random access through virtual offsets
+pub struct BgzfReader<R: Read> {
+    inner: R,
+    block: Vec<u8>,
+    // position in `block` of the next byte to hand out
+    pos: usize,
+    block_offset: u64,
+    next_block_offset: u64,
+}
+
+impl<R: Read> BgzfReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            block: vec![],
+            pos: 0,
+            block_offset: 0,
+            next_block_offset: 0,
+        }
+    }
+
+    /// Virtual offset of the next byte to be read
+    pub fn virtual_offset(&self) -> u64 {
+        virtual_offset(self.block_offset, self.pos as u16)
+    }
+
+    /// Decompress the block at the current position of `inner`, returns
+    /// false at the end of the file
+    fn read_block(&mut self) -> io::Result<bool> {
+        let mut raw = vec![0u8; FIXED_HEADER_SIZE];
+        let mut n = 0;
+        while n < raw.len() {
+            match self.inner.read(&mut raw[n..])? {
+                0 if n == 0 => return Ok(false),
+                0 => return Err(InflateError::UnexpectedEof.into()),
+                len => n += len,
+            }
+        }
+        let invalid = |reason| io::Error::from(InflateError::InvalidHeader(reason));
+        if raw[3] & (1 << 2) == 0 {
+            return Err(invalid("BGZF block without FEXTRA"));
+        }
+        let extra_len = u16::from_le_bytes([raw[10], raw[11]]) as usize;
+        raw.resize(FIXED_HEADER_SIZE + extra_len, 0);
+        self.inner.read_exact(&mut raw[FIXED_HEADER_SIZE..])?;
+        let block_size = ExtraSubfield::parse(&raw[FIXED_HEADER_SIZE..])
+            .and_then(|subfields| {
+                subfields
+                    .into_iter()
+                    .find(|subfield| subfield.id == BLOCK_SIZE_ID && subfield.data.len() == 2)
+            })
+            .map(|subfield| u16::from_le_bytes([subfield.data[0], subfield.data[1]]) as usize + 1)
+            .ok_or_else(|| invalid("missing BC subfield"))?;
+        if block_size < raw.len() + 8 {
+            return Err(invalid("BC block size is too small"));
+        }
+        let header_len = raw.len();
+        raw.resize(block_size, 0);
+        self.inner.read_exact(&mut raw[header_len..])?;
+
+        self.block = GZip::inflate_member(&raw)?.data;
+        self.pos = 0;
+        self.block_offset = self.next_block_offset;
+        self.next_block_offset += block_size as u64;
+        Ok(true)
+    }
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Jump to a virtual offset from `BgzfWriter::virtual_offset` or
+    /// `BgzfReader::virtual_offset`, only the block there is decompressed
+    pub fn seek_virtual(&mut self, virtual_offset: u64) -> io::Result<()> {
+        let block_offset = virtual_offset >> 16;
+        let data_offset = (virtual_offset & 0xffff) as usize;
+        self.inner.seek(SeekFrom::Start(block_offset))?;
+        self.next_block_offset = block_offset;
+        if !self.read_block()? {
+            self.block.clear();
+            self.block_offset = block_offset;
+        }
+        if data_offset > self.block.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "virtual offset is past the end of its block",
+            ));
+        }
+        self.pos = data_offset;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for BgzfReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // skip empty blocks such as the EOF marker
+        while self.pos == self.block.len() {
+            if !self.read_block()? {
+                break;
+            }
+        }
+        Ok(&self.block[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_bgzf() {
+        let mut writer = BgzfWriter::new(vec![]).level(1);
+        let mut records = vec![];
+        for i in 0..20000 {
+            let record = format!("read{i}\tchr1\t{}\tACGTTGCA\n", i * 37);
+            records.push((writer.virtual_offset(), record.clone()));
+            writer.write_all(record.as_bytes()).unwrap();
+        }
+        let data = writer.finish().unwrap();
+        assert!(data.starts_with(&EOF_MARKER[..16]));
+        assert!(data.ends_with(&EOF_MARKER));
+        assert_eq!(write_block(b"", DEFAULT_LEVEL), EOF_MARKER);
+        let input: String = records.iter().map(|(_, record)| record.as_str()).collect();
+        assert!(input.len() > 3 * MAX_BLOCK_DATA);
+        // every block is a plain gzip member
+        assert_eq!(GZip::inflate(&data).unwrap(), input.as_bytes());
+
+        let mut reader = BgzfReader::new(Cursor::new(&data));
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, input);
+
+        for i in [15000, 3, 19999, 7777] {
+            let (offset, record) = &records[i];
+            reader.seek_virtual(*offset).unwrap();
+            assert_eq!(reader.virtual_offset(), *offset);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(&line, record);
+        }
+        assert!(records[19999].0 >> 16 > 0);
+
+        let mut reader = BgzfReader::new(&EOF_MARKER[..]);
+        assert_eq!(reader.read(&mut [0; 8]).unwrap(), 0);
+    }
+}
@@ -0,0 +1,45 @@
+use std::{
+    env,
+    io::{self, Write},
+    process,
+};
+
+use compression_rs::gzstat::GzStat;
+
+const USAGE: &str = "usage: gzstat [--no-headers] [--no-block-stats] [--print-block-codes] [--decode-blocks] [--utc] < input.gz
+
+Modification times are printed in local time like gzstat.py, or in UTC with --utc.";
+
+/// Analyze the gzip file on stdin, with the same options and output as
+/// gzstat.py
+fn main() {
+    let mut gzstat = GzStat::new();
+    for arg in env::args().skip(1) {
+        gzstat = match arg.as_str() {
+            "--no-headers" => gzstat.headers(false),
+            "--no-block-stats" => gzstat.block_stats(false),
+            "--print-block-codes" => gzstat.block_codes(true),
+            "--decode-blocks" => gzstat.decode_blocks(true),
+            "--utc" => gzstat.utc(true),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ => {
+                eprintln!("{USAGE}");
+                process::exit(2);
+            }
+        };
+    }
+    let stats = gzstat.analyze(io::stdin().lock());
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    if let Err(err) = gzstat
+        .write_report(&stats, &mut out)
+        .and_then(|_| out.flush())
+    {
+        if err.kind() != io::ErrorKind::BrokenPipe {
+            eprintln!("gzstat: {err}");
+            process::exit(1);
+        }
+    }
+}
@@ -1,7 +1,10 @@
 #![allow(unused)]
 
 use core::fmt;
-use std::{collections::LinkedList, io::BufReader};
+use std::{
+    collections::LinkedList,
+    io::{self, BufReader, Read},
+};
 
 use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize)]
@@ -32,6 +35,18 @@ impl Code {
     pub fn new(data: Numeric, len: u8) -> Self {
         Self { data, len }
     }
+
+    pub fn value(&self) -> usize {
+        usize::from(self.data)
+    }
+
+    pub fn len(&self) -> u8 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -148,6 +163,18 @@ impl BitIO {
         }
     }
 
+    /// Take the bytes that are already complete, a partially written last
+    /// byte stays behind for the next writes
+    pub fn drain_bytes(&mut self) -> Vec<u8> {
+        assert!(self.read == 0, "cannot drain after reading from the front");
+        let complete = self.len / 8;
+        let rest = self.data.split_off(complete);
+        self.len -= complete * 8;
+        std::mem::replace(&mut self.data, rest)
+            .into_iter()
+            .collect()
+    }
+
     fn rev_u8(data: u8) -> u8 {
         let s = format!("{data:08b}");
         let s: String = s.chars().rev().collect();
@@ -186,6 +213,111 @@ impl<'a> Reader<'a> {
     }
 }
 
+/// Read bits from a byte source, least significant bit first like DEFLATE
+/// packs them. Bytes are pulled one at a time only when needed, so after
+/// `align_byte` the inner reader sits exactly at the next unread byte.
+pub struct BitReader<R> {
+    inner: R,
+    bit_buf: u32,
+    bit_count: u8,
+    bytes_read: u64,
+}
+
+impl<R: Read> BitReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            bit_buf: 0,
+            bit_count: 0,
+            bytes_read: 0,
+        }
+    }
+
+    fn next_byte(&mut self) -> io::Result<u8> {
+        let mut buf = [0u8];
+        self.inner.read_exact(&mut buf)?;
+        self.bytes_read += 1;
+        Ok(buf[0])
+    }
+
+    pub fn read_bits(&mut self, n: u8) -> io::Result<u32> {
+        assert!(n <= 24);
+        while self.bit_count < n {
+            self.bit_buf |= (self.next_byte()? as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let res = self.bit_buf & ((1u32 << n) - 1);
+        self.bit_buf >>= n;
+        self.bit_count -= n;
+        Ok(res)
+    }
+
+    pub fn read_bit(&mut self) -> io::Result<bool> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    /// Drop the remaining bits of the current byte
+    pub fn align_byte(&mut self) {
+        let skip = self.bit_count % 8;
+        self.bit_buf >>= skip;
+        self.bit_count -= skip;
+    }
+
+    pub fn read_u8_align(&mut self) -> io::Result<u8> {
+        self.align_byte();
+        if self.bit_count >= 8 {
+            return Ok(self.read_bits(8)? as u8);
+        }
+        self.next_byte()
+    }
+
+    /// Whether all the input has been consumed, this may buffer one byte
+    /// ahead which later reads pick up
+    pub fn is_at_end(&mut self) -> io::Result<bool> {
+        if self.bit_count > 0 {
+            return Ok(false);
+        }
+        let mut buf = [0u8];
+        loop {
+            match self.inner.read(&mut buf) {
+                Ok(0) => return Ok(true),
+                Ok(_) => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        self.bytes_read += 1;
+        self.bit_buf = buf[0] as u32;
+        self.bit_count = 8;
+        Ok(false)
+    }
+
+    pub fn read_u16_align_little_endian(&mut self) -> io::Result<u16> {
+        let low = self.read_u8_align()? as u16;
+        let high = self.read_u8_align()? as u16;
+        Ok(low | (high << 8))
+    }
+
+    pub fn read_u32_align_little_endian(&mut self) -> io::Result<u32> {
+        let low = self.read_u16_align_little_endian()? as u32;
+        let high = self.read_u16_align_little_endian()? as u32;
+        Ok(low | (high << 16))
+    }
+
+    /// Number of bits consumed so far
+    pub fn bit_position(&self) -> u64 {
+        self.bytes_read * 8 - self.bit_count as u64
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
 impl fmt::Debug for BitIO {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut len = self.len;
@@ -275,6 +407,35 @@ mod tests {
         assert_eq!(handler.read_bit_back(), None);
     }
 
+    #[test]
+    fn test_bit_reader() {
+        let input: &[u8] = &[0b1010_1101, 0xff, 0x34, 0x12];
+        let mut reader = BitReader::new(input);
+        assert!(reader.read_bit().unwrap());
+        assert_eq!(reader.read_bits(3).unwrap(), 0b110);
+        assert_eq!(reader.read_bits(6).unwrap(), 0b11_1010);
+        assert_eq!(reader.bit_position(), 10);
+        assert!(!reader.is_at_end().unwrap());
+        assert_eq!(reader.read_u16_align_little_endian().unwrap(), 0x1234);
+        assert!(reader.is_at_end().unwrap());
+        assert_eq!(
+            reader.read_bit().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_drain_bytes() {
+        let mut handler = BitIO::new(LinkedList::new());
+        handler.write_byte(0xab);
+        handler.write_bit_back(true);
+        assert_eq!(handler.drain_bytes(), [0xab]);
+        assert!(handler.drain_bytes().is_empty());
+        handler.write_code(&Code::new(Numeric::U8(0b11), 7));
+        assert_eq!(handler.drain_bytes(), [0b111]);
+        assert!(handler.is_empty());
+    }
+
     #[test]
     fn test_write() {
         let mut handler = BitIO::new(LinkedList::new());
@@ -2,43 +2,204 @@
 
 use std::{
     collections::LinkedList,
-    io::{BufReader, Read},
+    fs::OpenOptions,
+    io::{self, BufReader, Read, Write},
+    ops::Range,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
     time::SystemTime,
 };
 
-use crate::bit_io::{BitIO, Code, Numeric, Reader};
+use crate::{
+    bit_io::{BitIO, BitReader, Code, Numeric, Reader},
+    huffman::{canonical_codes, code_lengths},
+    inflate::{InflateError, Inflater},
+    optimal::optimal_parse,
+    stream::GzEncoder,
+    LZ77::{self, Parsing, Value},
+};
 
 const GZIP_ID1: u8 = 0x1f;
 const GZIP_ID2: u8 = 0x8b;
 const DEFLATE_METHOD: u8 = 8;
+const FLAG_TEXT: u8 = 1;
+const FLAG_HCRC: u8 = 1 << 1;
+const FLAG_EXTRA: u8 = 1 << 2;
+const FLAG_NAME: u8 = 1 << 3;
+const FLAG_COMMENT: u8 = 1 << 4;
+const FLAG_RESERVED: u8 = 0b1110_0000;
+const OS_UNIX: u8 = 3;
+const XFL_MAX_COMPRESSION: u8 = 2;
+const XFL_FASTEST: u8 = 4;
+
+pub(crate) const WINDOW_SIZE: usize = 32768;
+pub(crate) const MIN_MATCH: usize = 3;
+pub(crate) const MAX_MATCH: usize = 258;
+pub const DEFAULT_LEVEL: u8 = 6;
+pub const MAX_LEVEL: u8 = 9;
+// most LZ77 tokens we put into one compressed block
+const BLOCK_TOKENS: usize = 16384;
+// granularity at which block splitting considers starting a new block
+const SPLIT_TOKENS: usize = 2048;
+const END_OF_BLOCK: usize = 256;
+const MAX_CODE_LENGTH: u8 = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: u8 = 7;
+
+/// Match-finder effort of a compression level, refer to
+/// `configuration_table` in https://github.com/madler/zlib/blob/master/deflate.c
+struct LevelConfig {
+    good_length: usize,
+    lazy_length: usize,
+    nice_length: usize,
+    max_chain: usize,
+    parsing: Parsing,
+}
+
+const fn level_config(
+    good_length: usize,
+    lazy_length: usize,
+    nice_length: usize,
+    max_chain: usize,
+    parsing: Parsing,
+) -> LevelConfig {
+    LevelConfig {
+        good_length,
+        lazy_length,
+        nice_length,
+        max_chain,
+        parsing,
+    }
+}
+
+const LAZY: Parsing = Parsing::Lazy { depth: 1 };
+
+// level 0 stores the input, levels 1-3 match greedily and the rest lazily
+const LEVEL_CONFIGS: [LevelConfig; 10] = [
+    level_config(0, 0, 0, 0, Parsing::Greedy),
+    level_config(4, 0, 8, 4, Parsing::Greedy),
+    level_config(4, 0, 16, 8, Parsing::Greedy),
+    level_config(4, 0, 32, 32, Parsing::Greedy),
+    level_config(4, 4, 16, 16, LAZY),
+    level_config(8, 16, 32, 32, LAZY),
+    level_config(8, 16, 128, 128, LAZY),
+    level_config(8, 32, 128, 256, LAZY),
+    level_config(32, 128, 258, 1024, LAZY),
+    level_config(32, 258, 258, 4096, LAZY),
+];
+
+// refer to https://www.rfc-editor.org/rfc/rfc1951 S3.2.5
+pub(crate) const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+pub(crate) const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+pub(crate) const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(crate) const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// refer to https://www.rfc-editor.org/rfc/rfc1951 S3.2.7
+pub(crate) const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
 
 // refer to https://www.rfc-editor.org/rfc/rfc1952.pdf S2.3
-struct GZipHeader {
-    id1: u8,
-    id2: u8,
-    compression_method: u8,
-    flag: u8,
-    modification_time: u32,
-    extra_flag: u8,
-    os: u8,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GZipHeader {
+    /// FTEXT, the content is probably ASCII text
+    pub is_text: bool,
+    pub modification_time: u32,
+    pub extra_flag: u8,
+    pub os: u8,
+    /// FEXTRA payload, without the XLEN prefix
+    pub extra: Option<Vec<u8>>,
+    /// FNAME, the original file name without the terminating zero
+    pub filename: Option<Vec<u8>>,
+    /// FCOMMENT, without the terminating zero
+    pub comment: Option<Vec<u8>>,
+    /// FHCRC, a CRC16 of the header follows it
+    pub has_header_crc: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GZipFooter {
+    pub crc32: u32,
+    /// size of the original input modulo 2^32
+    pub input_size: u32,
 }
 
-struct GZipFooter {
-    crc32: u32,
-    input_size: u32,
+/// One decompressed gzip member
+#[derive(Debug)]
+pub struct GZipMember {
+    pub header: GZipHeader,
+    pub data: Vec<u8>,
 }
 
-struct GZip {
+pub struct GZip {
     header: GZipHeader,
     footer: GZipFooter,
     compressed_block: Option<Vec<Block>>,
     bit_io: BitIO,
 }
 
+/// One `SI1 SI2 LEN data` record of the gzip FEXTRA field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtraSubfield {
+    pub id: [u8; 2],
+    pub data: Vec<u8>,
+}
+
+/// Configure the header fields and compression of a gzip member
+///
+/// The defaults match `GZip::deflate`: the current time as mtime, unix as
+/// the OS and no optional fields.
+pub struct GZipBuilder {
+    header: GZipHeader,
+    level: u8,
+    strategy: BlockStrategy,
+}
+
+/// How `flush` ends the compressed data written so far, both leave the
+/// stream open for more input
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlushMode {
+    /// Emit everything pending followed by an empty stored block, so the
+    /// output ends on a byte boundary and a decoder can produce all of it
+    Sync,
+    /// Like `Sync`, and later matches never refer back past this point, so
+    /// decoding can also start here
+    Full,
+}
+
+/// Which kind of block `GZip` emits for the compressed data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockStrategy {
+    NoCompression,
+    FixedHuff,
+    DynamicHuff,
+    /// Split where a fresh tree pays off and give each block whichever of
+    /// stored, fixed or dynamic encoding takes the fewest bits
+    Adaptive,
+    /// Like `Adaptive` on top of a Zopfli-style optimal parse, many times
+    /// slower than level 9 for a few percent smaller output
+    Optimal,
+}
+
 enum BlockType {
-    NoCompression { len: u16 },
+    NoCompression {
+        len: u16,
+    },
     FixedHuffCompression,
-    DynamicHuffCompression,
+    DynamicHuffCompression {
+        literal_lengths: Vec<u8>,
+        distance_lengths: Vec<u8>,
+    },
     Error,
 }
 
@@ -59,27 +220,29 @@ impl GZip {
     }
 
     pub fn deflate(input: &[u8]) -> BitIO {
-        let header = GZipHeader {
-            id1: GZIP_ID1,
-            id2: GZIP_ID2,
-            compression_method: DEFLATE_METHOD,
-            flag: 0,
-            modification_time: SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as u32,
-            extra_flag: 0,
-            os: 0x03,
-        };
+        Self::deflate_with(input, BlockStrategy::Adaptive)
+    }
+
+    pub fn deflate_with(input: &[u8], strategy: BlockStrategy) -> BitIO {
+        GZipBuilder::new().strategy(strategy).deflate(input)
+    }
+
+    /// Compress with a level from 0 (store only) to 9 (slowest, smallest)
+    pub fn deflate_level(input: &[u8], level: u8) -> BitIO {
+        GZipBuilder::new().level(level).deflate(input)
+    }
+
+    /// Compress on `threads` threads, see `GZipBuilder::deflate_parallel`
+    pub fn deflate_parallel(input: &[u8], threads: usize) -> BitIO {
+        GZipBuilder::new().deflate_parallel(input, threads)
+    }
+
+    fn compress(header: GZipHeader, input: &[u8], level: u8, strategy: BlockStrategy) -> BitIO {
         let footer = GZipFooter {
-            crc32: crc32fast::hash(&input),
+            crc32: crc32fast::hash(input),
             input_size: input.len() as u32,
         };
-        let mut reader = Reader::new(input);
-        let mut blocks = vec![];
-        while !reader.is_empty() {
-            blocks.push(Self::write_block(&mut reader));
-        }
+        let blocks = compress_blocks(input, 0, level, strategy, true);
 
         let mut zip = Self::new(header, footer);
 
@@ -90,32 +253,40 @@ impl GZip {
         zip.bit_io
     }
 
-    fn write_block(reader: &mut Reader) -> Block {
-        Self::write_no_compression_block(reader)
+    /// Decompress a gzip file, checking the CRC32 and size of every member.
+    /// The output of all members is concatenated.
+    pub fn inflate(input: &[u8]) -> Result<Vec<u8>, InflateError> {
+        let mut reader = BitReader::new(input);
+        let mut data = Self::read_member(&mut reader)?.data;
+        while !reader.is_at_end()? {
+            data.extend(Self::read_member(&mut reader)?.data);
+        }
+        Ok(data)
     }
 
-    fn write_no_compression_block(reader: &mut Reader) -> Block {
-        let mut len = 0u16;
-        let mut bit_io = BitIO::new(LinkedList::new());
-        while !reader.is_empty() {
-            if len == u16::MAX {
-                // hit max size, jump to next block
-                return Block {
-                    is_final: false,
-                    _type: BlockType::NoCompression { len },
-                    data: bit_io,
-                };
-            }
-            let data = reader.read_u8().unwrap();
-            len += 1;
-            bit_io.write_byte_align(data);
-        }
-        // reader is empty, so this is the last block
-        Block {
-            is_final: true,
-            _type: BlockType::NoCompression { len },
-            data: bit_io,
+    /// Decompress the first gzip member and return it along with its header
+    pub fn inflate_member(input: &[u8]) -> Result<GZipMember, InflateError> {
+        Self::read_member(&mut BitReader::new(input))
+    }
+
+    /// Decompress every member of a gzip file, each with its own header
+    pub fn inflate_members(input: &[u8]) -> Result<Vec<GZipMember>, InflateError> {
+        let mut reader = BitReader::new(input);
+        let mut members = vec![Self::read_member(&mut reader)?];
+        while !reader.is_at_end()? {
+            members.push(Self::read_member(&mut reader)?);
         }
+        Ok(members)
+    }
+
+    fn read_member<R: Read>(reader: &mut BitReader<R>) -> Result<GZipMember, InflateError> {
+        let header = GZipHeader::read(reader)?;
+        let mut inflater = Inflater::new();
+        inflater.inflate(reader)?;
+        let data = inflater.into_output();
+        let footer = GZipFooter::read(reader)?;
+        footer.verify(&data)?;
+        Ok(GZipMember { header, data })
     }
 
     fn write(&mut self) {
@@ -131,6 +302,778 @@ impl GZip {
     }
 }
 
+/// Compress `input` into a raw DEFLATE stream: just the block sequence,
+/// without any header or trailer
+pub fn deflate_raw(input: &[u8], strategy: BlockStrategy) -> BitIO {
+    let mut bit_io = BitIO::new(LinkedList::new());
+    write_deflate(input, &[], DEFAULT_LEVEL, strategy, &mut bit_io);
+    bit_io
+}
+
+/// Like `deflate_raw`, with a compression level from 0 to 9
+pub fn deflate_raw_level(input: &[u8], level: u8) -> BitIO {
+    let mut bit_io = BitIO::new(LinkedList::new());
+    write_deflate(input, &[], level, BlockStrategy::Adaptive, &mut bit_io);
+    bit_io
+}
+
+/// Like `deflate_raw_level` with the window primed with a preset
+/// dictionary, the decoder needs the same dictionary to get `input` back
+pub fn deflate_raw_dictionary(input: &[u8], dictionary: &[u8], level: u8) -> BitIO {
+    let mut bit_io = BitIO::new(LinkedList::new());
+    write_deflate(
+        input,
+        dictionary,
+        level,
+        BlockStrategy::Adaptive,
+        &mut bit_io,
+    );
+    bit_io
+}
+
+/// Write `input` as a sequence of DEFLATE blocks, the last one marked final.
+/// Matches may refer back into the last `WINDOW_SIZE` bytes of `dictionary`.
+pub(crate) fn write_deflate(
+    input: &[u8],
+    dictionary: &[u8],
+    level: u8,
+    strategy: BlockStrategy,
+    bit_io: &mut BitIO,
+) {
+    let dictionary = &dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..];
+    let buffer = [dictionary, input].concat();
+    for mut block in compress_blocks(&buffer, dictionary.len(), level, strategy, true) {
+        block.write(bit_io);
+    }
+}
+
+/// Bytes of input `Deflater` collects before compressing them into blocks
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// Incremental DEFLATE compressor. Input is compressed a chunk at a time
+/// and only the last `WINDOW_SIZE` bytes are kept around as history, so
+/// matches still reach back across chunks while memory stays bounded.
+pub(crate) struct Deflater {
+    level: u8,
+    strategy: BlockStrategy,
+    // history followed by the input that is not compressed yet
+    buffer: Vec<u8>,
+    pending: usize,
+    bit_io: BitIO,
+}
+
+impl Deflater {
+    pub(crate) fn new(level: u8, strategy: BlockStrategy) -> Self {
+        assert!(level <= MAX_LEVEL, "compression level must be 0 to 9");
+        Self {
+            level,
+            strategy,
+            buffer: Vec::with_capacity(WINDOW_SIZE + CHUNK_SIZE),
+            pending: 0,
+            bit_io: BitIO::new(LinkedList::new()),
+        }
+    }
+
+    /// Take in as much of `input` as fits into the current chunk, returns
+    /// how many bytes were consumed
+    pub(crate) fn write(&mut self, input: &[u8]) -> usize {
+        let n = input.len().min(CHUNK_SIZE - self.pending);
+        self.buffer.extend_from_slice(&input[..n]);
+        self.pending += n;
+        if self.pending == CHUNK_SIZE {
+            self.compress(false);
+        }
+        n
+    }
+
+    /// Compress whatever is pending and end the stream with a final block
+    pub(crate) fn finish(&mut self) {
+        self.compress(true);
+    }
+
+    /// Compress what is pending without ending the stream
+    pub(crate) fn flush(&mut self, mode: FlushMode) {
+        self.compress(false);
+        write_sync_flush(&mut self.bit_io);
+        if mode == FlushMode::Full {
+            self.buffer.clear();
+        }
+    }
+
+    /// Where the compressed blocks go, container formats write their
+    /// header and trailer here too
+    pub(crate) fn bit_io_mut(&mut self) -> &mut BitIO {
+        &mut self.bit_io
+    }
+
+    /// Compressed bytes that are complete so far
+    pub(crate) fn take_output(&mut self) -> Vec<u8> {
+        self.bit_io.drain_bytes()
+    }
+
+    /// Whatever output was not taken yet, the last byte padded with zero
+    /// bits, for when nothing else follows the stream
+    pub(crate) fn into_output(self) -> Vec<u8> {
+        self.bit_io.as_vec()
+    }
+
+    fn compress(&mut self, is_final: bool) {
+        if self.pending == 0 && !is_final {
+            return;
+        }
+        let start = self.buffer.len() - self.pending;
+        for mut block in compress_blocks(&self.buffer, start, self.level, self.strategy, is_final) {
+            block.write(&mut self.bit_io);
+        }
+        self.pending = 0;
+        let keep = self.buffer.len().saturating_sub(WINDOW_SIZE);
+        self.buffer.drain(..keep);
+    }
+}
+
+/// An empty stored block, its LEN field starts on a byte boundary so the
+/// output so far ends on one too
+fn write_sync_flush(bit_io: &mut BitIO) {
+    let mut empty = Block::no_compression(&mut Reader::new(&[]));
+    empty.is_final = false;
+    empty.write(bit_io);
+}
+
+/// Bytes of input each worker of `GZipBuilder::deflate_parallel` takes at a time
+const PARALLEL_CHUNK_SIZE: usize = 1 << 17;
+
+/// Compress `input` in independent chunks on `threads` threads, pigz-style.
+/// Each chunk uses the 32K before it as a dictionary and all but the last
+/// end with a sync flush, so the outputs simply follow each other. Returns
+/// the DEFLATE stream and the CRC32 of `input`.
+fn deflate_parallel(
+    input: &[u8],
+    level: u8,
+    strategy: BlockStrategy,
+    threads: usize,
+) -> (Vec<u8>, u32) {
+    assert!(threads > 0, "need at least one thread");
+    let mut chunks: Vec<Range<usize>> = (0..input.len())
+        .step_by(PARALLEL_CHUNK_SIZE)
+        .map(|start| start..(start + PARALLEL_CHUNK_SIZE).min(input.len()))
+        .collect();
+    if chunks.is_empty() {
+        chunks.push(0..0);
+    }
+    // workers take the next chunk until none are left
+    let next = AtomicUsize::new(0);
+    let compress_chunk = |i: usize| {
+        let chunk = &chunks[i];
+        let is_final = i == chunks.len() - 1;
+        let history = chunk.start.saturating_sub(WINDOW_SIZE);
+        let mut bit_io = BitIO::new(LinkedList::new());
+        for mut block in compress_blocks(
+            &input[history..chunk.end],
+            chunk.start - history,
+            level,
+            strategy,
+            is_final,
+        ) {
+            block.write(&mut bit_io);
+        }
+        if !is_final {
+            write_sync_flush(&mut bit_io);
+        }
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&input[chunk.clone()]);
+        (i, bit_io.as_vec(), crc)
+    };
+    let mut results: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(chunks.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= chunks.len() {
+                            break done;
+                        }
+                        done.push(compress_chunk(i));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|&(i, _, _)| i);
+
+    let mut output = vec![];
+    let mut crc = crc32fast::Hasher::new();
+    for (_, data, chunk_crc) in results {
+        output.extend(data);
+        crc.combine(&chunk_crc);
+    }
+    (output, crc.finalize())
+}
+
+/// The LZ77 codec searching as hard as `level` asks for
+fn level_codec(level: u8) -> LZ77::Codec {
+    assert!(level <= MAX_LEVEL, "compression level must be 0 to 9");
+    let config = &LEVEL_CONFIGS[level as usize];
+    LZ77::Codec::new(WINDOW_SIZE, MAX_MATCH, MIN_MATCH)
+        .max_chain(config.max_chain)
+        .nice_length(config.nice_length)
+        .good_length(config.good_length)
+        .lazy_length(config.lazy_length)
+        .parsing(config.parsing)
+}
+
+/// Blocks for `input[start..]`, matches may refer back into `input[..start]`.
+/// Only when `is_final` is the last block marked final.
+fn compress_blocks(
+    input: &[u8],
+    start: usize,
+    level: u8,
+    strategy: BlockStrategy,
+    is_final: bool,
+) -> Vec<Block> {
+    assert!(level <= MAX_LEVEL, "compression level must be 0 to 9");
+    // level 0 stores everything regardless of the requested strategy
+    let strategy = if level == 0 {
+        BlockStrategy::NoCompression
+    } else {
+        strategy
+    };
+    let mut blocks = match strategy {
+        BlockStrategy::NoCompression => stored_blocks(&input[start..]),
+        BlockStrategy::Optimal => {
+            let tokens = optimal_parse(input, start);
+            tokens_to_blocks(&tokens, &input[start..], strategy)
+        }
+        _ => {
+            let tokens = level_codec(level).encode_from(input, start);
+            tokens_to_blocks(&tokens, &input[start..], strategy)
+        }
+    };
+    blocks.last_mut().unwrap().is_final = is_final;
+    blocks
+}
+
+/// Stored blocks of at most 65535 bytes, always at least one (possibly
+/// empty) block
+fn stored_blocks(data: &[u8]) -> Vec<Block> {
+    let mut reader = Reader::new(data);
+    let mut blocks = vec![Block::no_compression(&mut reader)];
+    while !reader.is_empty() {
+        blocks.push(Block::no_compression(&mut reader));
+    }
+    blocks
+}
+
+/// Blocks for the tokens encoding `data`, the last one marked final. An
+/// empty input still gets a final block to end the stream.
+fn tokens_to_blocks(tokens: &[Value], data: &[u8], strategy: BlockStrategy) -> Vec<Block> {
+    let ranges = match strategy {
+        BlockStrategy::FixedHuff => {
+            // one fixed tree fits every block, only the size limits them
+            let mut ranges: Vec<_> = (0..tokens.len())
+                .step_by(BLOCK_TOKENS)
+                .map(|start| start..(start + BLOCK_TOKENS).min(tokens.len()))
+                .collect();
+            if ranges.is_empty() {
+                ranges.push(0..0);
+            }
+            ranges
+        }
+        _ => split_blocks(tokens),
+    };
+    let mut blocks = vec![];
+    let mut offset = 0;
+    for (i, range) in ranges.iter().enumerate() {
+        let tokens = &tokens[range.clone()];
+        let is_final = i == ranges.len() - 1;
+        let len: usize = tokens
+            .iter()
+            .map(|token| match *token {
+                Value::Literal(_) => 1,
+                Value::BackRef { len, .. } => len,
+            })
+            .sum();
+        if matches!(strategy, BlockStrategy::Adaptive | BlockStrategy::Optimal) {
+            blocks.extend(Block::cheapest(
+                tokens,
+                &data[offset..offset + len],
+                is_final,
+            ));
+        } else {
+            blocks.push(Block::new(tokens, is_final, strategy));
+        }
+        offset += len;
+    }
+    blocks
+}
+
+/// Where to end dynamic blocks: every `SPLIT_TOKENS` tokens we check
+/// whether coding the next stretch with a tree of its own, header
+/// included, beats extending the current block's tree over it
+fn split_blocks(tokens: &[Value]) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    let mut end = (start + SPLIT_TOKENS).min(tokens.len());
+    while end < tokens.len() {
+        let next = (end + SPLIT_TOKENS).min(tokens.len());
+        let separate =
+            dynamic_block_bits(&tokens[start..end]) + dynamic_block_bits(&tokens[end..next]);
+        if next - start > BLOCK_TOKENS || separate < dynamic_block_bits(&tokens[start..next]) {
+            ranges.push(start..end);
+            start = end;
+        }
+        end = next;
+    }
+    ranges.push(start..end);
+    ranges
+}
+
+/// Exact size in bits of a dynamic block holding `tokens`
+pub(crate) fn dynamic_block_bits(tokens: &[Value]) -> usize {
+    let (literal_lengths, distance_lengths) = dynamic_lengths(tokens);
+    let (literal_counts, distance_counts) = token_counts(tokens);
+    3 + dynamic_header_bits(
+        &trim_lengths(literal_lengths.clone(), 257),
+        &trim_lengths(distance_lengths.clone(), 1),
+    ) + data_bits(
+        &literal_counts,
+        &distance_counts,
+        &literal_lengths,
+        &distance_lengths,
+    )
+}
+
+/// Exact size in bits of a fixed block holding `tokens`
+fn fixed_block_bits(tokens: &[Value]) -> usize {
+    let (literal_counts, distance_counts) = token_counts(tokens);
+    3 + data_bits(
+        &literal_counts,
+        &distance_counts,
+        &fixed_literal_lengths(),
+        &fixed_distance_lengths(),
+    )
+}
+
+/// Size in bits of `len` bytes as stored blocks, not counting the padding
+/// up to the byte boundary which depends on where the block starts
+fn stored_block_bits(len: usize) -> usize {
+    let blocks = len.div_ceil(u16::MAX as usize).max(1);
+    blocks * (3 + 32) + len * 8
+}
+
+/// Bits the symbols and extra bits of a block take with these code lengths
+fn data_bits(
+    literal_counts: &[usize],
+    distance_counts: &[usize],
+    literal_lengths: &[u8],
+    distance_lengths: &[u8],
+) -> usize {
+    let mut bits = 0;
+    for (symbol, &n) in literal_counts.iter().enumerate() {
+        bits += n * literal_lengths[symbol] as usize;
+        if symbol > END_OF_BLOCK {
+            bits += n * LENGTH_EXTRA[symbol - END_OF_BLOCK - 1] as usize;
+        }
+    }
+    for (symbol, &n) in distance_counts.iter().enumerate() {
+        bits += n * (distance_lengths[symbol] + DISTANCE_EXTRA[symbol]) as usize;
+    }
+    bits
+}
+
+// refer to https://www.rfc-editor.org/rfc/rfc1951 S3.2.6
+pub(crate) fn fixed_literal_lengths() -> [u8; 288] {
+    let mut lengths = [0u8; 288];
+    lengths[0..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..288].fill(8);
+    lengths
+}
+
+pub(crate) fn fixed_distance_lengths() -> [u8; 30] {
+    [5; 30]
+}
+
+/// Map a match length to (length symbol, extra bits, extra value)
+pub(crate) fn length_symbol(len: usize) -> (usize, u8, usize) {
+    assert!((MIN_MATCH..=MAX_MATCH).contains(&len));
+    let index = LENGTH_BASE.partition_point(|&base| base as usize <= len) - 1;
+    (
+        257 + index,
+        LENGTH_EXTRA[index],
+        len - LENGTH_BASE[index] as usize,
+    )
+}
+
+/// Map a match distance to (distance symbol, extra bits, extra value)
+pub(crate) fn distance_symbol(distance: usize) -> (usize, u8, usize) {
+    assert!((1..=WINDOW_SIZE).contains(&distance));
+    let index = DISTANCE_BASE.partition_point(|&base| base as usize <= distance) - 1;
+    (
+        index,
+        DISTANCE_EXTRA[index],
+        distance - DISTANCE_BASE[index] as usize,
+    )
+}
+
+/// How often each symbol appears in a block of `tokens`, end-of-block included
+fn token_counts(tokens: &[Value]) -> ([usize; 286], [usize; 30]) {
+    let mut literal_counts = [0usize; 286];
+    let mut distance_counts = [0usize; 30];
+    for token in tokens {
+        match *token {
+            Value::Literal(byte) => literal_counts[byte as usize] += 1,
+            Value::BackRef { len, distance } => {
+                literal_counts[length_symbol(len).0] += 1;
+                distance_counts[distance_symbol(distance).0] += 1;
+            }
+        }
+    }
+    literal_counts[END_OF_BLOCK] = 1;
+    (literal_counts, distance_counts)
+}
+
+/// Symbol frequencies to build the dynamic trees from
+pub(crate) fn token_frequencies(tokens: &[Value]) -> ([usize; 286], [usize; 30]) {
+    let (mut literal_freq, mut distance_freq) = token_counts(tokens);
+    // decoders want at least two codes in each tree (like zlib does),
+    // so a lone symbol gets a sibling it never uses
+    for freq in [&mut literal_freq[..], &mut distance_freq[..]] {
+        for symbol in 0..2 {
+            if freq.iter().filter(|&&f| f > 0).count() < 2 && freq[symbol] == 0 {
+                freq[symbol] = 1;
+            }
+        }
+    }
+    (literal_freq, distance_freq)
+}
+
+/// Drop trailing unused code lengths, keeping at least `min_len` entries
+fn trim_lengths(mut lengths: Vec<u8>, min_len: usize) -> Vec<u8> {
+    while lengths.len() > min_len && lengths.last() == Some(&0) {
+        lengths.pop();
+    }
+    lengths
+}
+
+/// Run-length encode code lengths with the code length alphabet,
+/// refer to https://www.rfc-editor.org/rfc/rfc1951 S3.2.7
+///
+/// Returns (symbol, extra bits value) pairs, symbols 16, 17, 18 carry
+/// 2, 3, 7 extra bits respectively.
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut res = vec![];
+    let mut i = 0;
+    while i < lengths.len() {
+        let len = lengths[i];
+        let mut run = lengths[i..].iter().take_while(|&&l| l == len).count();
+        i += run;
+        if len == 0 {
+            while run >= 11 {
+                let n = run.min(138);
+                res.push((18, (n - 11) as u8));
+                run -= n;
+            }
+            if run >= 3 {
+                res.push((17, (run - 3) as u8));
+                run = 0;
+            }
+        } else {
+            res.push((len, 0));
+            run -= 1;
+            while run >= 3 {
+                let n = run.min(6);
+                res.push((16, (n - 3) as u8));
+                run -= n;
+            }
+        }
+        res.extend(std::iter::repeat_n((len, 0), run));
+    }
+    res
+}
+
+/// Code lengths of the dynamic trees for a block of `tokens`
+fn dynamic_lengths(tokens: &[Value]) -> (Vec<u8>, Vec<u8>) {
+    let (literal_freq, distance_freq) = token_frequencies(tokens);
+    (
+        code_lengths(&literal_freq, MAX_CODE_LENGTH),
+        code_lengths(&distance_freq, MAX_CODE_LENGTH),
+    )
+}
+
+/// Run-length encoded code lengths of a dynamic header along with the code
+/// length code lengths and how many of those the header carries (HCLEN + 4)
+fn code_length_code(
+    literal_lengths: &[u8],
+    distance_lengths: &[u8],
+) -> (Vec<(u8, u8)>, Vec<u8>, usize) {
+    // the two sets of lengths are run-length encoded as one sequence
+    let all_lengths: Vec<u8> = literal_lengths
+        .iter()
+        .chain(distance_lengths)
+        .copied()
+        .collect();
+    let encoded = run_length_encode(&all_lengths);
+    let mut freq = [0usize; 19];
+    for &(symbol, _) in &encoded {
+        freq[symbol as usize] += 1;
+    }
+    let cl_lengths = code_lengths(&freq, MAX_CODE_LENGTH_CODE_LENGTH);
+    let hclen = CODE_LENGTH_ORDER
+        .iter()
+        .rposition(|&symbol| cl_lengths[symbol] != 0)
+        .map_or(4, |pos| (pos + 1).max(4));
+    (encoded, cl_lengths, hclen)
+}
+
+fn code_length_extra_bits(symbol: u8) -> u8 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// Size in bits of the dynamic header written by `write_dynamic_header`
+fn dynamic_header_bits(literal_lengths: &[u8], distance_lengths: &[u8]) -> usize {
+    let (encoded, cl_lengths, hclen) = code_length_code(literal_lengths, distance_lengths);
+    let lengths_bits: usize = encoded
+        .iter()
+        .map(|&(symbol, _)| (cl_lengths[symbol as usize] + code_length_extra_bits(symbol)) as usize)
+        .sum();
+    5 + 5 + 4 + 3 * hclen + lengths_bits
+}
+
+fn write_dynamic_header(literal_lengths: &[u8], distance_lengths: &[u8], bit_io: &mut BitIO) {
+    let (encoded, cl_lengths, hclen) = code_length_code(literal_lengths, distance_lengths);
+    let cl_codes = canonical_codes(&cl_lengths);
+
+    bit_io.write_code(&Code::new(Numeric::Usize(literal_lengths.len() - 257), 5));
+    bit_io.write_code(&Code::new(Numeric::Usize(distance_lengths.len() - 1), 5));
+    bit_io.write_code(&Code::new(Numeric::Usize(hclen - 4), 4));
+    for &symbol in &CODE_LENGTH_ORDER[..hclen] {
+        bit_io.write_code(&Code::new(Numeric::U8(cl_lengths[symbol]), 3));
+    }
+    for (symbol, extra) in encoded {
+        bit_io.write_code_rev(&cl_codes[symbol as usize]);
+        bit_io.write_code(&Code::new(
+            Numeric::U8(extra),
+            code_length_extra_bits(symbol),
+        ));
+    }
+}
+
+/// Write LZ77 tokens followed by the end-of-block symbol
+fn write_tokens(
+    tokens: &[Value],
+    literal_codes: &[Code],
+    distance_codes: &[Code],
+    bit_io: &mut BitIO,
+) {
+    for token in tokens {
+        match *token {
+            Value::Literal(byte) => bit_io.write_code_rev(&literal_codes[byte as usize]),
+            Value::BackRef { len, distance } => {
+                let (symbol, extra_len, extra) = length_symbol(len);
+                bit_io.write_code_rev(&literal_codes[symbol]);
+                bit_io.write_code(&Code::new(Numeric::Usize(extra), extra_len));
+                let (symbol, extra_len, extra) = distance_symbol(distance);
+                bit_io.write_code_rev(&distance_codes[symbol]);
+                bit_io.write_code(&Code::new(Numeric::Usize(extra), extra_len));
+            }
+        }
+    }
+    bit_io.write_code_rev(&literal_codes[END_OF_BLOCK]);
+}
+
+impl Default for GZipBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GZipBuilder {
+    pub fn new() -> Self {
+        Self {
+            header: GZipHeader {
+                is_text: false,
+                modification_time: SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as u32,
+                extra_flag: 0,
+                os: OS_UNIX,
+                extra: None,
+                filename: None,
+                comment: None,
+                has_header_crc: false,
+            },
+            level: DEFAULT_LEVEL,
+            strategy: BlockStrategy::Adaptive,
+        }
+    }
+
+    /// Original file name, an `InvalidInput` error if it contains a zero byte
+    pub fn filename(mut self, filename: impl AsRef<[u8]>) -> io::Result<Self> {
+        self.header.filename = Some(zero_terminated_field(filename.as_ref(), "FNAME")?);
+        Ok(self)
+    }
+
+    /// Comment, an `InvalidInput` error if it contains a zero byte
+    pub fn comment(mut self, comment: impl AsRef<[u8]>) -> io::Result<Self> {
+        self.header.comment = Some(zero_terminated_field(comment.as_ref(), "FCOMMENT")?);
+        Ok(self)
+    }
+
+    /// Append a subfield to FEXTRA, an `InvalidInput` error if FEXTRA would
+    /// grow beyond 65535 bytes
+    pub fn extra_subfield(mut self, id: [u8; 2], data: &[u8]) -> io::Result<Self> {
+        let extra = self.header.extra.get_or_insert_with(Vec::new);
+        if extra.len() + 4 + data.len() > u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FEXTRA is too long",
+            ));
+        }
+        ExtraSubfield {
+            id,
+            data: data.to_vec(),
+        }
+        .write(extra);
+        Ok(self)
+    }
+
+    /// Seconds since the unix epoch, 0 means no time stamp is available
+    /// which also makes the output reproducible
+    pub fn modification_time(mut self, modification_time: u32) -> Self {
+        self.header.modification_time = modification_time;
+        self
+    }
+
+    pub fn os(mut self, os: u8) -> Self {
+        self.header.os = os;
+        self
+    }
+
+    /// XFL, 2 for maximum compression, 4 for the fastest algorithm
+    pub fn extra_flag(mut self, extra_flag: u8) -> Self {
+        self.header.extra_flag = extra_flag;
+        self
+    }
+
+    pub fn text(mut self, is_text: bool) -> Self {
+        self.header.is_text = is_text;
+        self
+    }
+
+    /// Protect the header with a CRC16
+    pub fn header_crc(mut self, has_header_crc: bool) -> Self {
+        self.header.has_header_crc = has_header_crc;
+        self
+    }
+
+    /// Compression level from 0 (store only) to 9 (slowest, smallest).
+    /// Like GNU gzip this also sets XFL: 2 for level 9, 4 for level 1.
+    pub fn level(mut self, level: u8) -> Self {
+        assert!(level <= MAX_LEVEL, "compression level must be 0 to 9");
+        self.level = level;
+        self.header.extra_flag = match level {
+            MAX_LEVEL => XFL_MAX_COMPRESSION,
+            1 => XFL_FASTEST,
+            _ => 0,
+        };
+        self
+    }
+
+    pub fn strategy(mut self, strategy: BlockStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn header(&self) -> &GZipHeader {
+        &self.header
+    }
+
+    pub fn deflate(&self, input: &[u8]) -> BitIO {
+        GZip::compress(self.header.clone(), input, self.level, self.strategy)
+    }
+
+    /// Streaming compressor writing a member with this header to `inner`
+    pub fn encoder<W: Write>(&self, inner: W) -> GzEncoder<W> {
+        GzEncoder::with_header(inner, &self.header, self.level, self.strategy)
+    }
+
+    /// Like `deflate`, with the input split into chunks that are compressed
+    /// on `threads` threads. The result is a single ordinary gzip member,
+    /// slightly larger than `deflate` would make it.
+    pub fn deflate_parallel(&self, input: &[u8], threads: usize) -> BitIO {
+        let (data, crc32) = deflate_parallel(input, self.level, self.strategy, threads);
+        let mut bit_io = BitIO::new(LinkedList::new());
+        self.header.write(&mut bit_io);
+        for byte in data {
+            bit_io.write_byte_align(byte);
+        }
+        GZipFooter {
+            crc32,
+            input_size: input.len() as u32,
+        }
+        .write(&mut bit_io);
+        bit_io
+    }
+
+    /// Compress `input` as a new member at the end of the gzip file at
+    /// `path`, the members already there are left untouched
+    pub fn append_to_file(&self, path: impl AsRef<Path>, input: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(&self.deflate(input).as_vec())
+    }
+}
+
+/// FNAME and FCOMMENT end with a zero byte, so they can't contain one
+fn zero_terminated_field(field: &[u8], name: &str) -> io::Result<Vec<u8>> {
+    if field.contains(&0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name} can't contain a zero byte"),
+        ));
+    }
+    Ok(field.to_vec())
+}
+
+impl ExtraSubfield {
+    fn write(&self, extra: &mut Vec<u8>) {
+        assert!(self.data.len() <= u16::MAX as usize);
+        extra.extend(self.id);
+        extra.extend((self.data.len() as u16).to_le_bytes());
+        extra.extend(&self.data);
+    }
+
+    /// Split a FEXTRA payload into its subfields, `None` if it isn't
+    /// made of well-formed records
+    pub fn parse(mut extra: &[u8]) -> Option<Vec<Self>> {
+        let mut res = vec![];
+        while !extra.is_empty() {
+            if extra.len() < 4 {
+                return None;
+            }
+            let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+            let data = extra.get(4..4 + len)?;
+            res.push(Self {
+                id: [extra[0], extra[1]],
+                data: data.to_vec(),
+            });
+            extra = &extra[4 + len..];
+        }
+        Some(res)
+    }
+}
+
 impl BlockType {
     pub fn write(&self, bit_io: &mut BitIO) {
         match self {
@@ -147,10 +1090,14 @@ impl BlockType {
                 bit_io.write_bit_back(true);
                 bit_io.write_bit_back(false);
             }
-            BlockType::DynamicHuffCompression => {
+            BlockType::DynamicHuffCompression {
+                literal_lengths,
+                distance_lengths,
+            } => {
                 // 10
                 bit_io.write_bit_back(false);
                 bit_io.write_bit_back(true);
+                write_dynamic_header(literal_lengths, distance_lengths, bit_io);
             }
             BlockType::Error => {
                 // 11
@@ -162,6 +1109,86 @@ impl BlockType {
 }
 
 impl Block {
+    fn new(tokens: &[Value], is_final: bool, strategy: BlockStrategy) -> Self {
+        match strategy {
+            BlockStrategy::DynamicHuff => Self::dynamic_huffman(tokens, is_final),
+            _ => Self::fixed_huffman(tokens, is_final),
+        }
+    }
+
+    /// Whichever of stored, fixed or dynamic takes the fewest bits for the
+    /// tokens encoding `data`. Ties go to the simpler encoding, so a block
+    /// never ends up larger than its stored form.
+    fn cheapest(tokens: &[Value], data: &[u8], is_final: bool) -> Vec<Block> {
+        let stored = stored_block_bits(data.len());
+        let fixed = fixed_block_bits(tokens);
+        let dynamic = dynamic_block_bits(tokens);
+        if stored <= fixed.min(dynamic) {
+            let mut blocks = stored_blocks(data);
+            blocks.last_mut().unwrap().is_final = is_final;
+            blocks
+        } else if fixed <= dynamic {
+            vec![Self::fixed_huffman(tokens, is_final)]
+        } else {
+            vec![Self::dynamic_huffman(tokens, is_final)]
+        }
+    }
+
+    fn dynamic_huffman(tokens: &[Value], is_final: bool) -> Block {
+        let (literal_lengths, distance_lengths) = dynamic_lengths(tokens);
+        let mut bit_io = BitIO::new(LinkedList::new());
+        write_tokens(
+            tokens,
+            &canonical_codes(&literal_lengths),
+            &canonical_codes(&distance_lengths),
+            &mut bit_io,
+        );
+        Block {
+            is_final,
+            _type: BlockType::DynamicHuffCompression {
+                literal_lengths: trim_lengths(literal_lengths, 257),
+                distance_lengths: trim_lengths(distance_lengths, 1),
+            },
+            data: bit_io,
+        }
+    }
+
+    fn fixed_huffman(tokens: &[Value], is_final: bool) -> Block {
+        let literal_codes = canonical_codes(&fixed_literal_lengths());
+        let distance_codes = canonical_codes(&fixed_distance_lengths());
+        let mut bit_io = BitIO::new(LinkedList::new());
+        write_tokens(tokens, &literal_codes, &distance_codes, &mut bit_io);
+        Block {
+            is_final,
+            _type: BlockType::FixedHuffCompression,
+            data: bit_io,
+        }
+    }
+
+    fn no_compression(reader: &mut Reader) -> Block {
+        let mut len = 0u16;
+        let mut bit_io = BitIO::new(LinkedList::new());
+        while !reader.is_empty() {
+            if len == u16::MAX {
+                // hit max size, jump to next block
+                return Block {
+                    is_final: false,
+                    _type: BlockType::NoCompression { len },
+                    data: bit_io,
+                };
+            }
+            let data = reader.read_u8().unwrap();
+            len += 1;
+            bit_io.write_byte_align(data);
+        }
+        // reader is empty, so this is the last block
+        Block {
+            is_final: true,
+            _type: BlockType::NoCompression { len },
+            data: bit_io,
+        }
+    }
+
     pub fn write(&mut self, bit_io: &mut BitIO) {
         if self.is_final {
             bit_io.write_bit_back(true);
@@ -174,14 +1201,124 @@ impl Block {
 }
 
 impl GZipHeader {
+    /// The FLG byte for the fields present
+    pub fn flags(&self) -> u8 {
+        let mut flag = 0;
+        for (present, field) in [
+            (self.is_text, FLAG_TEXT),
+            (self.has_header_crc, FLAG_HCRC),
+            (self.extra.is_some(), FLAG_EXTRA),
+            (self.filename.is_some(), FLAG_NAME),
+            (self.comment.is_some(), FLAG_COMMENT),
+        ] {
+            if present {
+                flag |= field;
+            }
+        }
+        flag
+    }
+
+    /// Panics on fields `GZipBuilder` would have refused
     pub fn write(&self, bit_io: &mut BitIO) {
-        bit_io.write_byte_align(self.id1);
-        bit_io.write_byte_align(self.id2);
-        bit_io.write_byte_align(self.compression_method);
-        bit_io.write_byte_align(self.flag);
-        bit_io.write_u32_align_little_endian(self.modification_time);
-        bit_io.write_byte_align(self.extra_flag);
-        bit_io.write_byte_align(self.os);
+        let mut raw = vec![GZIP_ID1, GZIP_ID2, DEFLATE_METHOD, self.flags()];
+        raw.extend(self.modification_time.to_le_bytes());
+        raw.push(self.extra_flag);
+        raw.push(self.os);
+        if let Some(extra) = &self.extra {
+            assert!(extra.len() <= u16::MAX as usize, "FEXTRA is too long");
+            raw.extend((extra.len() as u16).to_le_bytes());
+            raw.extend(extra);
+        }
+        for field in [&self.filename, &self.comment].into_iter().flatten() {
+            assert!(!field.contains(&0), "FNAME and FCOMMENT can't contain zero");
+            raw.extend(field);
+            raw.push(0);
+        }
+        if self.has_header_crc {
+            let crc16 = crc32fast::hash(&raw) as u16;
+            raw.extend(crc16.to_le_bytes());
+        }
+
+        for byte in raw {
+            bit_io.write_byte_align(byte);
+        }
+    }
+
+    /// The FEXTRA subfields, `None` if there is no FEXTRA or it's malformed
+    pub fn extra_subfields(&self) -> Option<Vec<ExtraSubfield>> {
+        ExtraSubfield::parse(self.extra.as_ref()?)
+    }
+
+    pub fn read<R: Read>(reader: &mut BitReader<R>) -> Result<Self, InflateError> {
+        // keep every byte we read, the header CRC16 covers all of them
+        let mut raw = vec![];
+        let mut next = |reader: &mut BitReader<R>| -> Result<u8, InflateError> {
+            let byte = reader.read_u8_align()?;
+            raw.push(byte);
+            Ok(byte)
+        };
+
+        if next(reader)? != GZIP_ID1 || next(reader)? != GZIP_ID2 {
+            return Err(InflateError::InvalidHeader("not a gzip file"));
+        }
+        if next(reader)? != DEFLATE_METHOD {
+            return Err(InflateError::InvalidHeader("unknown compression method"));
+        }
+        let flag = next(reader)?;
+        if flag & FLAG_RESERVED != 0 {
+            return Err(InflateError::InvalidHeader("reserved flag bits are set"));
+        }
+        let mut modification_time = 0u32;
+        for i in 0..4 {
+            modification_time |= (next(reader)? as u32) << (8 * i);
+        }
+        let extra_flag = next(reader)?;
+        let os = next(reader)?;
+
+        let extra = if flag & FLAG_EXTRA != 0 {
+            let len = next(reader)? as usize | (next(reader)? as usize) << 8;
+            let mut extra = Vec::with_capacity(len);
+            for _ in 0..len {
+                extra.push(next(reader)?);
+            }
+            Some(extra)
+        } else {
+            None
+        };
+        let mut read_zero_terminated =
+            |reader: &mut BitReader<R>, field: u8| -> Result<Option<Vec<u8>>, InflateError> {
+                if flag & field == 0 {
+                    return Ok(None);
+                }
+                let mut res = vec![];
+                loop {
+                    match next(reader)? {
+                        0 => return Ok(Some(res)),
+                        byte => res.push(byte),
+                    }
+                }
+            };
+        let filename = read_zero_terminated(reader, FLAG_NAME)?;
+        let comment = read_zero_terminated(reader, FLAG_COMMENT)?;
+
+        let has_header_crc = flag & FLAG_HCRC != 0;
+        if has_header_crc {
+            let crc16 = reader.read_u16_align_little_endian()?;
+            if crc16 != crc32fast::hash(&raw) as u16 {
+                return Err(InflateError::HeaderCrcMismatch);
+            }
+        }
+
+        Ok(Self {
+            is_text: flag & FLAG_TEXT != 0,
+            modification_time,
+            extra_flag,
+            os,
+            extra,
+            filename,
+            comment,
+            has_header_crc,
+        })
     }
 }
 
@@ -190,17 +1327,448 @@ impl GZipFooter {
         bit_io.write_u32_align_little_endian(self.crc32);
         bit_io.write_u32_align_little_endian(self.input_size);
     }
+
+    pub fn read<R: Read>(reader: &mut BitReader<R>) -> Result<Self, InflateError> {
+        Ok(Self {
+            crc32: reader.read_u32_align_little_endian()?,
+            input_size: reader.read_u32_align_little_endian()?,
+        })
+    }
+
+    /// Check the footer against the decompressed data
+    pub fn verify(&self, data: &[u8]) -> Result<(), InflateError> {
+        self.check(&GZipFooter {
+            crc32: crc32fast::hash(data),
+            input_size: data.len() as u32,
+        })
+    }
+
+    /// Compare against the footer computed over the decompressed data
+    pub fn check(&self, actual: &GZipFooter) -> Result<(), InflateError> {
+        if actual.crc32 != self.crc32 {
+            return Err(InflateError::CrcMismatch {
+                expected: self.crc32,
+                actual: actual.crc32,
+            });
+        }
+        if actual.input_size != self.input_size {
+            return Err(InflateError::SizeMismatch {
+                expected: self.input_size,
+                actual: actual.input_size,
+            });
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::fs;
+    use std::{
+        fs,
+        io::Write,
+        process::{Command, Stdio},
+        thread,
+    };
 
     use super::*;
 
+    fn gzip(data: Vec<u8>) -> Vec<u8> {
+        let mut child = Command::new("gzip")
+            .arg("-c")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("run gzip");
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(&data).unwrap());
+        let output = child.wait_with_output().unwrap();
+        writer.join().unwrap();
+        output.stdout
+    }
+
+    // decompress with the system gzip so we know the output is standard
+    fn gunzip(data: Vec<u8>) -> Vec<u8> {
+        let mut child = Command::new("gzip")
+            .arg("-dc")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("run gzip");
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(&data).unwrap());
+        let output = child.wait_with_output().unwrap();
+        writer.join().unwrap();
+        assert!(output.status.success(), "gzip rejected our output");
+        output.stdout
+    }
+
+    #[test]
+    fn test_length_distance_symbol() {
+        assert_eq!(length_symbol(3), (257, 0, 0));
+        assert_eq!(length_symbol(12), (265, 1, 1));
+        assert_eq!(length_symbol(257), (284, 5, 30));
+        assert_eq!(length_symbol(258), (285, 0, 0));
+        assert_eq!(distance_symbol(1), (0, 0, 0));
+        assert_eq!(distance_symbol(6), (4, 1, 1));
+        assert_eq!(distance_symbol(32768), (29, 13, 8191));
+    }
+
+    #[test]
+    fn test_deflate_fixed_huffman() {
+        let inputs: [&[u8]; 4] = [
+            b"",
+            b"h",
+            b"A SALAD; A SALSA",
+            b"abcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabc",
+        ];
+        for input in inputs {
+            let data = GZip::deflate(input).as_vec();
+            assert_eq!(gunzip(data), input);
+        }
+
+        let input = b"hello world, hello deflate, hello gzip. ".repeat(20);
+        let data = GZip::deflate(&input).as_vec();
+        assert!(data.len() < input.len());
+        assert_eq!(gunzip(data), input);
+    }
+
+    #[test]
+    fn test_run_length_encode() {
+        assert_eq!(
+            run_length_encode(&[8, 8, 8, 8, 8, 0, 0, 0, 5, 0, 0]),
+            vec![(8, 0), (16, 1), (17, 0), (5, 0), (0, 0), (0, 0)]
+        );
+        let mut lengths = vec![0; 150];
+        lengths.extend([7; 9]);
+        assert_eq!(
+            run_length_encode(&lengths),
+            vec![(18, 127), (18, 1), (7, 0), (16, 3), (7, 0), (7, 0)]
+        );
+    }
+
+    #[test]
+    fn test_deflate_dynamic_huffman() {
+        let inputs: [&[u8]; 4] = [
+            b"",
+            b"h",
+            b"A SALAD; A SALSA",
+            b"abababababababababababababababababababababab",
+        ];
+        for input in inputs {
+            let data = GZip::deflate_with(input, BlockStrategy::DynamicHuff).as_vec();
+            assert_eq!(gunzip(data), input);
+        }
+
+        let input: Vec<u8> = (0..3000u32)
+            .map(|i| b"etaoinshrdlu "[(i * i % 97 % 13) as usize])
+            .collect();
+        let fixed = GZip::deflate_with(&input, BlockStrategy::FixedHuff).as_vec();
+        let dynamic = GZip::deflate_with(&input, BlockStrategy::DynamicHuff).as_vec();
+        assert!(dynamic.len() < fixed.len());
+        assert_eq!(gunzip(dynamic), input);
+    }
+
+    #[test]
+    fn test_deflate_adaptive() {
+        // the block sizes we compute are the ones we write
+        let input = b"A SALAD; A SALSA, A SALAD; A SALSA".repeat(3);
+        let tokens = level_codec(6).encode(&input);
+        for (bits, mut block) in [
+            (
+                fixed_block_bits(&tokens),
+                Block::fixed_huffman(&tokens, true),
+            ),
+            (
+                dynamic_block_bits(&tokens),
+                Block::dynamic_huffman(&tokens, true),
+            ),
+        ] {
+            let mut bit_io = BitIO::new(LinkedList::new());
+            block.write(&mut bit_io);
+            assert_eq!(bit_io.as_vec().len(), bits.div_ceil(8));
+        }
+
+        // random letters, then random digits, then noise nothing compresses
+        let mut seed = 1u32;
+        let mut random = |n: u32| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            ((seed >> 16) % n) as u8
+        };
+        let mut input: Vec<u8> = (0..6000).map(|_| b'a' + random(26)).collect();
+        input.extend((0..6000).map(|_| b'0' + random(10)).collect::<Vec<_>>());
+        let noise: Vec<u8> = (0..5000).map(|_| random(256)).collect();
+        input.extend(&noise);
+
+        let blocks = compress_blocks(&input, 0, 1, BlockStrategy::Adaptive, true);
+        assert!(blocks.len() >= 3);
+        assert!(matches!(
+            blocks[0]._type,
+            BlockType::DynamicHuffCompression { .. }
+        ));
+        assert!(matches!(
+            blocks.last().unwrap()._type,
+            BlockType::NoCompression { .. }
+        ));
+        let adaptive = GZip::deflate_level(&input, 1).as_vec();
+        let dynamic = GZipBuilder::new()
+            .level(1)
+            .strategy(BlockStrategy::DynamicHuff)
+            .deflate(&input)
+            .as_vec();
+        assert!(adaptive.len() < dynamic.len());
+        assert_eq!(gunzip(adaptive), input);
+
+        // never more than the stored form: 5 bytes of block header per 64K
+        let data = deflate_raw_level(&noise, 1).as_vec();
+        assert!(data.len() <= noise.len() + 5);
+    }
+
+    #[test]
+    fn test_deflate_parallel() {
+        let input: Vec<u8> = (0..300_000u64)
+            .map(|i| b"pigz splits the input into chunks "[(i * i % 97 % 34) as usize])
+            .collect();
+        let builder = GZipBuilder::new().level(1).modification_time(0);
+        let parallel = builder.deflate_parallel(&input, 4).as_vec();
+        let serial = builder.deflate_parallel(&input, 1).as_vec();
+        assert_eq!(parallel, serial);
+        // with the dictionary each of the 3 chunks costs only a little extra
+        assert!(parallel.len() < builder.deflate(&input).as_vec().len() + 3 * 64);
+        assert_eq!(GZip::inflate(&parallel).unwrap(), input);
+        assert_eq!(gunzip(parallel), input);
+
+        assert_eq!(gunzip(GZip::deflate_parallel(b"", 2).as_vec()), b"");
+    }
+
+    #[test]
+    fn test_inflate() {
+        let input = b"hello world, hello deflate, hello gzip. ".repeat(20);
+        for strategy in [
+            BlockStrategy::NoCompression,
+            BlockStrategy::FixedHuff,
+            BlockStrategy::DynamicHuff,
+            BlockStrategy::Adaptive,
+        ] {
+            let data = GZip::deflate_with(&input, strategy).as_vec();
+            assert_eq!(GZip::inflate(&data).unwrap(), input);
+        }
+
+        // gzip output has back-references that reach into earlier blocks
+        let input: Vec<u8> = (0..100000u64).map(|i| (i * i % 251 % 7) as u8).collect();
+        assert_eq!(GZip::inflate(&gzip(input.clone())).unwrap(), input);
+
+        assert!(matches!(
+            GZip::inflate(b"PK\x03\x04"),
+            Err(InflateError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn test_inflate_member_header() {
+        let path = std::env::temp_dir().join("compression_rs_header.txt");
+        fs::write(&path, b"a file with its name stored in the header").unwrap();
+        let output = Command::new("gzip").arg("-c").arg(&path).output().unwrap();
+        let mtime = fs::metadata(&path)
+            .unwrap()
+            .modified()
+            .unwrap()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        fs::remove_file(path).unwrap();
+
+        let member = GZip::inflate_member(&output.stdout).unwrap();
+        assert_eq!(member.data, b"a file with its name stored in the header");
+        assert_eq!(
+            member.header.filename.as_deref(),
+            Some(&b"compression_rs_header.txt"[..])
+        );
+        assert_eq!(member.header.modification_time, mtime);
+        assert_eq!(member.header.comment, None);
+
+        // hand-made header with every optional field
+        let mut data = vec![0x1f, 0x8b, 8, 0b11111, 0x78, 0x56, 0x34, 0x12, 2, 3];
+        data.extend([4, 0, b'A', b'B', 0, 0]);
+        data.extend(b"name\0comment\0");
+        let crc16 = crc32fast::hash(&data) as u16;
+        data.extend(crc16.to_le_bytes());
+        data.extend([0x03, 0x00]);
+        data.extend(crc32fast::hash(b"").to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        let member = GZip::inflate_member(&data).unwrap();
+        assert_eq!(
+            member.header,
+            GZipHeader {
+                is_text: true,
+                modification_time: 0x12345678,
+                extra_flag: 2,
+                os: 3,
+                extra: Some(vec![b'A', b'B', 0, 0]),
+                filename: Some(b"name".to_vec()),
+                comment: Some(b"comment".to_vec()),
+                has_header_crc: true,
+            }
+        );
+
+        let mut corrupted = data.clone();
+        corrupted[4] ^= 1;
+        assert!(matches!(
+            GZip::inflate_member(&corrupted),
+            Err(InflateError::HeaderCrcMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_deflate_header_fields() {
+        let builder = GZipBuilder::new()
+            .filename("hlm.txt")
+            .unwrap()
+            .comment("a chapter or two")
+            .unwrap()
+            .extra_subfield([b'A', b'P'], b"\x01\x02\x03")
+            .unwrap()
+            .extra_subfield([b'B', b'C'], b"\x1b\x00")
+            .unwrap()
+            .modification_time(0)
+            .os(255)
+            .extra_flag(2)
+            .text(true)
+            .header_crc(true);
+        let input = b"reproducible reproducible reproducible";
+        let data = builder.deflate(input).as_vec();
+        // bit-for-bit reproducible
+        assert_eq!(data, builder.deflate(input).as_vec());
+        assert_eq!(gunzip(data.clone()), input);
+
+        let member = GZip::inflate_member(&data).unwrap();
+        assert_eq!(&member.header, builder.header());
+        assert_eq!(member.header.modification_time, 0);
+        assert_eq!(
+            member.header.extra_subfields().unwrap(),
+            vec![
+                ExtraSubfield {
+                    id: [b'A', b'P'],
+                    data: vec![1, 2, 3]
+                },
+                ExtraSubfield {
+                    id: [b'B', b'C'],
+                    data: vec![0x1b, 0]
+                },
+            ]
+        );
+        assert_eq!(ExtraSubfield::parse(&[b'A', b'P', 5, 0, 1]), None);
+
+        // fields that can't be written are refused up front
+        let invalid = |res: io::Result<GZipBuilder>| res.err().unwrap().kind();
+        assert_eq!(
+            invalid(GZipBuilder::new().filename("a\0b")),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            invalid(GZipBuilder::new().comment(b"\0")),
+            io::ErrorKind::InvalidInput
+        );
+        let builder = GZipBuilder::new()
+            .extra_subfield([b'A', b'P'], &[0; 40000])
+            .unwrap();
+        assert_eq!(
+            invalid(builder.extra_subfield([b'A', b'P'], &[0; 30000])),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn test_multi_member() {
+        let mut data = GZip::deflate(b"first member, ").as_vec();
+        data.extend(gzip(b"second member, ".to_vec()));
+        data.extend(GZip::deflate(b"").as_vec());
+        data.extend(GZip::deflate_with(b"last member", BlockStrategy::NoCompression).as_vec());
+        assert_eq!(
+            GZip::inflate(&data).unwrap(),
+            b"first member, second member, last member"
+        );
+        assert_eq!(
+            gunzip(data.clone()),
+            b"first member, second member, last member"
+        );
+        assert_eq!(GZip::inflate_members(&data).unwrap().len(), 4);
+
+        // anything after a member must be another member
+        data.extend(b"garbage");
+        assert!(GZip::inflate(&data).is_err());
+
+        let path = std::env::temp_dir().join("compression_rs_append.gz");
+        let _ = fs::remove_file(&path);
+        GZipBuilder::new()
+            .filename("a.log")
+            .unwrap()
+            .append_to_file(&path, b"rotated log a\n")
+            .unwrap();
+        let first = fs::read(&path).unwrap();
+        GZipBuilder::new()
+            .filename("b.log")
+            .unwrap()
+            .append_to_file(&path, b"rotated log b\n")
+            .unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(data.starts_with(&first));
+
+        let members = GZip::inflate_members(&data).unwrap();
+        let names: Vec<_> = members
+            .iter()
+            .map(|m| m.header.filename.clone().unwrap())
+            .collect();
+        assert_eq!(names, vec![b"a.log".to_vec(), b"b.log".to_vec()]);
+        assert_eq!(gunzip(data), b"rotated log a\nrotated log b\n");
+    }
+
+    #[test]
+    fn test_deflate_level() {
+        let input: Vec<u8> = (0..4000u64)
+            .map(|i| b"etaoinshrdlu etaoin shrdlu "[(i * i % 101 % 27) as usize])
+            .collect();
+        let sizes: Vec<usize> = (0..=MAX_LEVEL)
+            .map(|level| {
+                let data = GZip::deflate_level(&input, level).as_vec();
+                assert_eq!(GZip::inflate(&data).unwrap(), input);
+                data.len()
+            })
+            .collect();
+        // level 0 only stores
+        assert!(sizes[0] > input.len());
+        assert!(sizes[9] <= sizes[1]);
+        assert_eq!(gunzip(GZip::deflate_level(&input, 9).as_vec()), input);
+
+        let header = |level| {
+            let data = GZip::deflate_level(b"xfl", level).as_vec();
+            GZip::inflate_member(&data).unwrap().header
+        };
+        assert_eq!(header(1).extra_flag, 4);
+        assert_eq!(header(6).extra_flag, 0);
+        assert_eq!(header(9).extra_flag, 2);
+    }
+
+    #[test]
+    fn test_inflate_footer() {
+        let mut data = GZip::deflate(b"check the trailer").as_vec();
+        let len = data.len();
+        data[len - 1] ^= 1;
+        assert!(matches!(
+            GZip::inflate(&data),
+            Err(InflateError::SizeMismatch { .. })
+        ));
+        data[len - 1] ^= 1;
+        data[len - 8] ^= 1;
+        assert!(matches!(
+            GZip::inflate(&data),
+            Err(InflateError::CrcMismatch { .. })
+        ));
+    }
+
     #[test]
     fn test_deflate_no_compression() {
-        let n = GZip::deflate(b"h");
+        let n = GZip::deflate_with(b"h", BlockStrategy::NoCompression);
         println!("{n:?}");
 
         let data = n.as_vec();
@@ -0,0 +1,240 @@
+#![allow(unused)]
+
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+
+use serde::{Deserialize, Serialize};
+use serde_cbor::{from_slice, ser::to_vec_packed};
+
+use crate::{
+    bit_io::BitReader,
+    deflate::{GZipFooter, GZipHeader, WINDOW_SIZE},
+    inflate::{InflateError, Inflater},
+};
+
+/// Decoder state at a block boundary: everything needed to resume inflating
+/// there without the data before it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// bits of compressed input before the next block header
+    pub input_bit: u64,
+    /// uncompressed bytes before this point
+    pub output: u64,
+    /// up to 32K of output before this point, what back references can reach
+    pub window: Vec<u8>,
+}
+
+/// Random-access index of a gzip file, refer to
+/// https://github.com/madler/zlib/blob/master/examples/zran.c
+///
+/// Building it inflates the whole file once and keeps a checkpoint about
+/// every `span` bytes of output. `read_at` then only decompresses from the
+/// checkpoint before the requested range. Concatenated members are covered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GzIndex {
+    checkpoints: Vec<Checkpoint>,
+    uncompressed_len: u64,
+}
+
+impl GzIndex {
+    /// Inflate `input` with a checkpoint about every `span` bytes of output,
+    /// the CRC32 and size of each member are checked along the way
+    pub fn build<R: Read>(input: R, span: u64) -> Result<Self, InflateError> {
+        assert!(span > 0);
+        let mut reader = BitReader::new(BufReader::new(input));
+        let mut checkpoints: Vec<Checkpoint> = vec![];
+        let mut total = 0u64;
+        loop {
+            GZipHeader::read(&mut reader)?;
+            let mut inflater = Inflater::new();
+            let mut crc = crc32fast::Hasher::new();
+            // uncompressed offset of inflater.output()[0]
+            let mut base = total;
+            let mut member_len = 0u64;
+            while !inflater.is_done() {
+                let output = base + inflater.output().len() as u64;
+                if checkpoints
+                    .last()
+                    .is_none_or(|last| output >= last.output + span)
+                {
+                    let window = inflater.output();
+                    checkpoints.push(Checkpoint {
+                        input_bit: reader.bit_position(),
+                        output,
+                        window: window[window.len().saturating_sub(WINDOW_SIZE)..].to_vec(),
+                    });
+                }
+                let start = inflater.output().len();
+                inflater.inflate_block(&mut reader)?;
+                crc.update(&inflater.output()[start..]);
+                member_len += (inflater.output().len() - start) as u64;
+                base += inflater.trim_history() as u64;
+            }
+            GZipFooter::read(&mut reader)?.check(&GZipFooter {
+                crc32: crc.finalize(),
+                input_size: member_len as u32,
+            })?;
+            total += member_len;
+            if reader.is_at_end()? {
+                break;
+            }
+        }
+        Ok(Self {
+            checkpoints,
+            uncompressed_len: total,
+        })
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// Size of the decompressed file
+    pub fn uncompressed_len(&self) -> u64 {
+        self.uncompressed_len
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        to_vec_packed(self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let index: Self =
+            from_slice(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        // what `read_at` relies on, `build` always makes it so
+        let checkpoints = &index.checkpoints;
+        let valid = checkpoints.first().is_some_and(|first| first.output == 0)
+            && checkpoints
+                .windows(2)
+                .all(|pair| pair[0].output <= pair[1].output)
+            && checkpoints
+                .iter()
+                .all(|checkpoint| checkpoint.window.len() as u64 <= checkpoint.output);
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "checkpoints must start at offset 0 and be in order",
+            ));
+        }
+        Ok(index)
+    }
+
+    /// Up to `len` bytes of decompressed data starting at `offset`, fewer
+    /// when the file ends first. `input` is the file the index was built
+    /// from.
+    pub fn read_at<R: Read + Seek>(
+        &self,
+        input: R,
+        offset: u64,
+        len: usize,
+    ) -> io::Result<Vec<u8>> {
+        let mut res = vec![];
+        if len == 0 || offset >= self.uncompressed_len {
+            return Ok(res);
+        }
+        let end = offset.saturating_add(len as u64);
+        let checkpoint = &self.checkpoints[self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.output <= offset)
+            - 1];
+
+        let mut input = input;
+        input.seek(SeekFrom::Start(checkpoint.input_bit / 8))?;
+        let mut reader = BitReader::new(BufReader::new(input));
+        reader.read_bits((checkpoint.input_bit % 8) as u8)?;
+        let mut inflater = Inflater::with_dictionary(&checkpoint.window);
+        let mut base = checkpoint.output - checkpoint.window.len() as u64;
+        loop {
+            let start = inflater.output().len();
+            inflater.inflate_until(&mut reader, start + WINDOW_SIZE)?;
+            let output = inflater.output();
+            // copy whatever part of the range this piece of output covers
+            let next = offset + res.len() as u64;
+            let stop = end.min(base + output.len() as u64);
+            if next < stop {
+                res.extend_from_slice(&output[(next - base) as usize..(stop - base) as usize]);
+            }
+            if res.len() == len {
+                break;
+            }
+            base += inflater.trim_history() as u64;
+
+            if inflater.is_done() {
+                // the whole member was checked while building the index
+                GZipFooter::read(&mut reader)?;
+                if reader.is_at_end()? {
+                    break;
+                }
+                GZipHeader::read(&mut reader)?;
+                base += inflater.output().len() as u64;
+                inflater = Inflater::new();
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::deflate::GZip;
+
+    #[test]
+    fn test_gz_index() {
+        let mut seed = 7u32;
+        let words = [
+            "index ", "gzip ", "zran ", "window ", "block ", "random ", "access\n",
+        ];
+        let input: Vec<u8> = (0..40000)
+            .flat_map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                words[(seed >> 16) as usize % words.len()].bytes()
+            })
+            .collect();
+        let mut data = GZip::deflate_level(&input, 1).as_vec();
+        data.extend(GZip::deflate(b"second member").as_vec());
+        let mut expected = input.clone();
+        expected.extend(b"second member");
+
+        let index = GzIndex::build(&data[..], 32 * 1024).unwrap();
+        assert_eq!(index.uncompressed_len(), expected.len() as u64);
+        assert!(index.checkpoints().len() > 2);
+        assert_eq!(index.checkpoints()[1].window.len(), WINDOW_SIZE);
+        let index = GzIndex::from_bytes(&index.to_bytes()).unwrap();
+
+        for (offset, len) in [
+            (0, 10),
+            (100_000, 5000),
+            (input.len() - 20, 30),
+            (expected.len() - 3, 100),
+            (123_456, 0),
+        ] {
+            let res = index
+                .read_at(Cursor::new(&data), offset as u64, len)
+                .unwrap();
+            let end = (offset + len).min(expected.len());
+            assert_eq!(res, expected[offset..end]);
+        }
+        assert!(index
+            .read_at(Cursor::new(&data), expected.len() as u64, 10)
+            .unwrap()
+            .is_empty());
+
+        let malformed = |checkpoints| {
+            let bytes = GzIndex {
+                checkpoints,
+                uncompressed_len: expected.len() as u64,
+            }
+            .to_bytes();
+            GzIndex::from_bytes(&bytes).err().unwrap().kind()
+        };
+        assert_eq!(malformed(vec![]), io::ErrorKind::InvalidData);
+        let mut checkpoints = index.checkpoints().to_vec();
+        checkpoints.remove(0);
+        assert_eq!(malformed(checkpoints), io::ErrorKind::InvalidData);
+
+        data[20] ^= 1;
+        assert!(GzIndex::build(&data[..], 32 * 1024).is_err());
+    }
+}
@@ -0,0 +1,633 @@
+#![allow(unused)]
+
+use std::io::{self, BufReader, Read, Write};
+
+use crate::{
+    bit_io::BitReader,
+    deflate::{
+        fixed_distance_lengths, fixed_literal_lengths, GZipFooter, GZipHeader, DISTANCE_BASE,
+        DISTANCE_EXTRA, LENGTH_BASE, LENGTH_EXTRA,
+    },
+    huffman::canonical_codes,
+    inflate::{decode_symbol, DynamicHeader, Huffman, InflateError, Inflater},
+};
+
+pub use crate::inflate::Symbol;
+
+const END_OF_BLOCK: u16 = 256;
+
+/// Structure of a gzip file block by block, a port of gzstat.py by B. Bird
+///
+/// `analyze` gives the structure as data, `write_report` prints it the way
+/// `python gzstat.py` does with the same options. The defaults match the
+/// script without arguments: headers and block stats only.
+pub struct GzStat {
+    headers: bool,
+    block_stats: bool,
+    block_codes: bool,
+    decode_blocks: bool,
+    utc: bool,
+}
+
+/// Everything read from a gzip file, up to the error if there is one
+#[derive(Debug)]
+pub struct GzStats {
+    pub members: Vec<MemberStats>,
+    pub error: Option<InflateError>,
+}
+
+#[derive(Debug)]
+pub struct MemberStats {
+    pub header: GZipHeader,
+    pub blocks: Vec<BlockStats>,
+    /// the footer as stored, None when the member is cut short
+    pub footer: Option<GZipFooter>,
+    /// CRC32 and size of the data decoded so far
+    pub actual: GZipFooter,
+}
+
+#[derive(Debug)]
+pub struct BlockStats {
+    pub is_final: bool,
+    pub kind: BlockKind,
+    /// where the block header starts in the file, in bits
+    pub start_bit: u64,
+    /// size of the block including its header, in bits
+    pub bits: u64,
+    /// the decoded literal/length/distance stream, only kept when
+    /// `decode_blocks` is on
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockKind {
+    Stored { len: u16, nlen: u16 },
+    Fixed,
+    Dynamic(DynamicHeader),
+}
+
+impl BlockKind {
+    fn type_id(&self) -> u8 {
+        match self {
+            BlockKind::Stored { .. } => 0,
+            BlockKind::Fixed => 1,
+            BlockKind::Dynamic(_) => 2,
+        }
+    }
+}
+
+impl Default for GzStat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GzStat {
+    pub fn new() -> Self {
+        Self {
+            headers: true,
+            block_stats: true,
+            block_codes: false,
+            decode_blocks: false,
+            utc: false,
+        }
+    }
+
+    /// Print the gzip header and footer fields, `--no-headers` turns it off
+    pub fn headers(mut self, headers: bool) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Print the type of each block, `--no-block-stats` turns it off
+    pub fn block_stats(mut self, block_stats: bool) -> Self {
+        self.block_stats = block_stats;
+        self
+    }
+
+    /// Print HLIT/HDIST/HCLEN and the code tables of dynamic blocks,
+    /// `--print-block-codes`
+    pub fn block_codes(mut self, block_codes: bool) -> Self {
+        self.block_codes = block_codes;
+        self
+    }
+
+    /// Keep and print every decoded symbol, `--decode-blocks`
+    pub fn decode_blocks(mut self, decode_blocks: bool) -> Self {
+        self.decode_blocks = decode_blocks;
+        self
+    }
+
+    /// Print modification times in UTC rather than local time, `--utc`
+    pub fn utc(mut self, utc: bool) -> Self {
+        self.utc = utc;
+        self
+    }
+
+    /// Walk every member of `input`, stopping at the first error
+    pub fn analyze<R: Read>(&self, input: R) -> GzStats {
+        let mut reader = BitReader::new(BufReader::new(input));
+        let mut stats = GzStats {
+            members: vec![],
+            error: None,
+        };
+        let mut next_member = |stats: &mut GzStats| -> Result<bool, InflateError> {
+            if reader.is_at_end()? {
+                return Ok(false);
+            }
+            let header = GZipHeader::read(&mut reader)?;
+            stats.members.push(MemberStats {
+                header,
+                blocks: vec![],
+                footer: None,
+                actual: GZipFooter {
+                    crc32: 0,
+                    input_size: 0,
+                },
+            });
+            self.analyze_member(&mut reader, stats.members.last_mut().unwrap())?;
+            Ok(true)
+        };
+        loop {
+            match next_member(&mut stats) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => {
+                    stats.error = Some(err);
+                    break;
+                }
+            }
+        }
+        stats
+    }
+
+    fn analyze_member<R: Read>(
+        &self,
+        reader: &mut BitReader<R>,
+        member: &mut MemberStats,
+    ) -> Result<(), InflateError> {
+        let mut inflater = Inflater::new();
+        let mut crc = crc32fast::Hasher::new();
+        loop {
+            let start_bit = reader.bit_position();
+            let is_final = reader.read_bit()?;
+            let kind = match reader.read_bits(2)? {
+                0 => BlockKind::Stored {
+                    len: reader.read_u16_align_little_endian()?,
+                    nlen: reader.read_u16_align_little_endian()?,
+                },
+                1 => BlockKind::Fixed,
+                2 => BlockKind::Dynamic(DynamicHeader::read(reader)?),
+                _ => return Err(InflateError::InvalidBlockType),
+            };
+            member.blocks.push(BlockStats {
+                is_final,
+                kind: kind.clone(),
+                start_bit,
+                bits: 0,
+                symbols: vec![],
+            });
+            let block = member.blocks.last_mut().unwrap();
+
+            let start = inflater.output().len();
+            match &kind {
+                BlockKind::Stored { len, nlen } => {
+                    if *len != !*nlen {
+                        return Err(InflateError::StoredLengthMismatch {
+                            len: *len,
+                            nlen: *nlen,
+                        });
+                    }
+                    for _ in 0..*len {
+                        inflater.push_symbol(Symbol::Literal(reader.read_u8_align()?))?;
+                    }
+                }
+                BlockKind::Fixed => {
+                    let literal = Huffman::new(&fixed_literal_lengths())?;
+                    let distance = Huffman::new(&fixed_distance_lengths())?;
+                    self.decode_codes(reader, &literal, &distance, &mut inflater, block)?;
+                }
+                BlockKind::Dynamic(header) => {
+                    let literal = Huffman::new(header.literal_lengths())?;
+                    literal.check_complete()?;
+                    let distance = Huffman::new(header.distance_lengths())?;
+                    distance.check_complete()?;
+                    self.decode_codes(reader, &literal, &distance, &mut inflater, block)?;
+                }
+            }
+            block.bits = reader.bit_position() - start_bit;
+
+            let output = &inflater.output()[start..];
+            crc.update(output);
+            member.actual = GZipFooter {
+                crc32: crc.clone().finalize(),
+                input_size: member.actual.input_size.wrapping_add(output.len() as u32),
+            };
+            inflater.trim_history();
+            if is_final {
+                break;
+            }
+        }
+        member.footer = Some(GZipFooter::read(reader)?);
+        Ok(())
+    }
+
+    /// Decode the codes of a block up to its end-of-block symbol
+    fn decode_codes<R: Read>(
+        &self,
+        reader: &mut BitReader<R>,
+        literal: &Huffman,
+        distance: &Huffman,
+        inflater: &mut Inflater,
+        block: &mut BlockStats,
+    ) -> Result<(), InflateError> {
+        loop {
+            let symbol = decode_symbol(reader, literal, distance)?;
+            if self.decode_blocks {
+                block.symbols.push(symbol);
+            }
+            if !inflater.push_symbol(symbol)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Print `stats` line for line like gzstat.py does
+    pub fn write_report<W: Write>(&self, stats: &GzStats, out: &mut W) -> io::Result<()> {
+        for (index, member) in stats.members.iter().enumerate() {
+            writeln!(out, "-- gzip member {index} --")?;
+            self.write_member(member, out)?;
+        }
+        match &stats.error {
+            None => writeln!(out, "Read {} gzip members", stats.members.len()),
+            Some(InflateError::UnexpectedEof) => writeln!(out, "Unexpected end of stream"),
+            Some(err) => writeln!(out, "Decoding exception: {err}"),
+        }
+    }
+
+    fn write_member<W: Write>(&self, member: &MemberStats, out: &mut W) -> io::Result<()> {
+        let header = &member.header;
+        if self.headers {
+            writeln!(out, "    Compression Method: 8 (deflate)")?;
+            writeln!(out, "    Flags: 0x{:02x}", header.flags())?;
+            writeln!(
+                out,
+                "    Modification time: 0x{:08x} ({})",
+                header.modification_time,
+                ctime(header.modification_time, self.utc)
+            )?;
+            writeln!(out, "    Extra flags: 0x{:02x}", header.extra_flag)?;
+            writeln!(out, "    OS Type: {}", header.os)?;
+        }
+        if !(self.block_stats || self.block_codes || self.decode_blocks) {
+            return Ok(());
+        }
+
+        for (index, block) in member.blocks.iter().enumerate() {
+            self.write_block(index, block, out)?;
+        }
+        if let (true, Some(footer)) = (self.headers, member.footer) {
+            writeln!(out, "    Stored CRC32: 0x{:08x}", footer.crc32)?;
+            writeln!(out, "    Stored Decompressed size: {}", footer.input_size)?;
+            writeln!(out, "    Actual CRC32: 0x{:08x}", member.actual.crc32)?;
+            writeln!(
+                out,
+                "    Actual Decompressed Size: {}",
+                member.actual.input_size
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_block<W: Write>(
+        &self,
+        index: usize,
+        block: &BlockStats,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let type_id = block.kind.type_id();
+        if self.block_stats {
+            writeln!(
+                out,
+                "    -- Block {index} (last = {}) --",
+                block.is_final as u8
+            )?;
+            let name = ["uncompressed", "fixed codes", "dynamic codes"][type_id as usize];
+            writeln!(out, "        Block type: {type_id} ({name})")?;
+        }
+        let (literal_lengths, distance_lengths) = match &block.kind {
+            BlockKind::Stored { len, nlen } => {
+                writeln!(out, "    Decoding Block Type 00 (uncompressed):")?;
+                return writeln!(
+                    out,
+                    "        LEN = {len} (0x{len:04x}), NLEN = {nlen} (0x{nlen:04x})"
+                );
+            }
+            BlockKind::Fixed => {
+                if self.block_stats {
+                    writeln!(out, "        Decoding Block Type 01 (fixed codes):")?;
+                }
+                (
+                    fixed_literal_lengths().to_vec(),
+                    fixed_distance_lengths().to_vec(),
+                )
+            }
+            BlockKind::Dynamic(header) => {
+                if self.block_stats {
+                    writeln!(out, "        Decoding Block Type 10 (dynamic codes):")?;
+                }
+                if self.block_codes {
+                    self.write_dynamic_header(header, out)?;
+                }
+                (
+                    header.literal_lengths().to_vec(),
+                    header.distance_lengths().to_vec(),
+                )
+            }
+        };
+        if !self.decode_blocks {
+            return Ok(());
+        }
+
+        let literal_codes = code_strings(&literal_lengths);
+        let distance_codes = code_strings(&distance_lengths);
+        writeln!(out, "{:12}-- Decoded data --", "")?;
+        for symbol in &block.symbols {
+            let (literal, length, distance) = match *symbol {
+                Symbol::Literal(byte) => (byte as u16, None, None),
+                Symbol::EndOfBlock => (END_OF_BLOCK, None, None),
+                Symbol::Match {
+                    length_symbol,
+                    length,
+                    distance_symbol,
+                    distance,
+                } => (
+                    length_symbol,
+                    Some(length),
+                    Some((distance_symbol, distance)),
+                ),
+            };
+            let path = &literal_codes[literal as usize];
+            if (33..=127).contains(&literal) {
+                writeln!(
+                    out,
+                    "{:12}{path}: 0x{literal:02x} ({})",
+                    "", literal as u8 as char
+                )?;
+            } else {
+                writeln!(out, "{:12}{path}: 0x{literal:02x}", "")?;
+            }
+            let (Some(length), Some((distance_symbol, distance))) = (length, distance) else {
+                continue;
+            };
+            let index = (literal - END_OF_BLOCK - 1) as usize;
+            writeln!(out, "{:16}0x{literal:02x} is Length code {literal}", "")?;
+            writeln!(
+                out,
+                "{:16}{} extra length bits: Offset {}. Total length {length}",
+                "",
+                LENGTH_EXTRA[index],
+                length - LENGTH_BASE[index]
+            )?;
+            let index = distance_symbol as usize;
+            writeln!(
+                out,
+                "{:16}Distance code {}: {distance_symbol}",
+                "", distance_codes[index]
+            )?;
+            writeln!(
+                out,
+                "{:16}{} extra distance bits: Offset {}. Total distance {distance}",
+                "",
+                DISTANCE_EXTRA[index],
+                distance - DISTANCE_BASE[index]
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_dynamic_header<W: Write>(
+        &self,
+        header: &DynamicHeader,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let prefix = format!("{:12}", "");
+        let (hlit, hdist, hclen) = (header.hlit, header.hdist, header.hclen);
+        writeln!(
+            out,
+            "{prefix}Number of LL codes: {} (HLIT = {hlit})",
+            hlit as usize + 257
+        )?;
+        writeln!(
+            out,
+            "{prefix}Number of dist codes: {} (HDIST = {hdist})",
+            hdist as usize + 1
+        )?;
+        writeln!(
+            out,
+            "{prefix}Number of code length (CL) codes: {} (HCLEN = {hclen})",
+            hclen as usize + 4
+        )?;
+        let lengths: Vec<_> = header
+            .code_length_lengths
+            .iter()
+            .map(|len| len.to_string())
+            .collect();
+        writeln!(
+            out,
+            "{prefix}CL code lengths (0 - 18): {}",
+            lengths.join(" ")
+        )?;
+        write_code_table(&prefix, "CL codes:", &header.code_length_lengths, out)?;
+
+        let mut previous = 0;
+        for &(symbol, extra) in &header.code_length_symbols {
+            match symbol {
+                16 => writeln!(
+                    out,
+                    "{prefix}Symbol 16 (repeat count {}, repeating {previous})",
+                    extra + 3
+                )?,
+                17 => writeln!(out, "{prefix}Symbol 17 (repeat count {})", extra + 3)?,
+                18 => writeln!(
+                    out,
+                    "{prefix}Symbol 18 (repeat count {})",
+                    extra as u16 + 11
+                )?,
+                _ => {}
+            }
+            previous = match symbol {
+                16 => previous,
+                17 | 18 => 0,
+                len => len,
+            };
+        }
+        write_code_table(&prefix, "LL codes:", header.literal_lengths(), out)?;
+        write_code_table(&prefix, "dist codes:", header.distance_lengths(), out)
+    }
+}
+
+/// The canonical code of each symbol as a string of bits, first bit first
+fn code_strings(lengths: &[u8]) -> Vec<String> {
+    canonical_codes(lengths)
+        .iter()
+        .map(|code| format!("{:0width$b}", code.value(), width = code.len() as usize))
+        .collect()
+}
+
+fn write_code_table<W: Write>(
+    prefix: &str,
+    title: &str,
+    lengths: &[u8],
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(out, "{prefix}{title}")?;
+    for (symbol, code) in code_strings(lengths).iter().enumerate() {
+        if lengths[symbol] != 0 {
+            writeln!(out, "{prefix}    {symbol}: {code}")?;
+        }
+    }
+    Ok(())
+}
+
+/// `secs` since the epoch like Python's `ctime`, e.g.
+/// `Thu Jan  1 00:00:00 1970`, in local time unless `utc`
+fn ctime(secs: u32, utc: bool) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = secs as i64 + if utc { 0 } else { utc_offset(secs) };
+    let days = secs.div_euclid(86400);
+    let time = secs.rem_euclid(86400);
+    // refer to http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{} {} {day:2} {:02}:{:02}:{:02} {year}",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        MONTHS[month as usize - 1],
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Seconds local time is ahead of UTC at `secs`, from the C library's
+/// `localtime_r` which reads `TZ` the same way Python's `time.ctime` does
+#[cfg(unix)]
+fn utc_offset(secs: u32) -> i64 {
+    use std::{
+        ffi::{c_char, c_int, c_long},
+        mem::MaybeUninit,
+    };
+
+    // the standard fields of `struct tm`, then the BSD/glibc/musl extensions
+    #[repr(C)]
+    struct Tm {
+        fields: [c_int; 9],
+        gmtoff: c_long,
+        zone: *const c_char,
+    }
+    extern "C" {
+        fn tzset();
+        fn localtime_r(time: *const c_long, tm: *mut Tm) -> *mut Tm;
+    }
+
+    let time = secs as c_long;
+    let mut tm = MaybeUninit::<Tm>::uninit();
+    // SAFETY: `localtime_r` only writes to `tm` and fills it in whenever it
+    // does not return null
+    unsafe {
+        tzset();
+        if localtime_r(&time, tm.as_mut_ptr()).is_null() {
+            return 0;
+        }
+        // `c_long` is only 32 bits on some targets
+        #[allow(clippy::unnecessary_cast)]
+        let gmtoff = tm.assume_init().gmtoff as i64;
+        gmtoff
+    }
+}
+
+#[cfg(not(unix))]
+fn utc_offset(secs: u32) -> i64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deflate::{BlockStrategy, GZipBuilder};
+
+    #[test]
+    fn test_gzstat() {
+        let input = b"abcabcabcabc hello hello hello \x01\x7f";
+        let mut data = vec![];
+        for strategy in [
+            BlockStrategy::NoCompression,
+            BlockStrategy::FixedHuff,
+            BlockStrategy::DynamicHuff,
+        ] {
+            data.extend(
+                GZipBuilder::new()
+                    .modification_time(0)
+                    .strategy(strategy)
+                    .deflate(input)
+                    .as_vec(),
+            );
+        }
+        let gzstat = GzStat::new()
+            .block_codes(true)
+            .decode_blocks(true)
+            .utc(true);
+        let stats = gzstat.analyze(&data[..]);
+        assert!(stats.error.is_none());
+        assert_eq!(stats.members.len(), 3);
+        for (member, type_id) in stats.members.iter().zip(0..) {
+            assert_eq!(member.blocks[0].kind.type_id(), type_id);
+            assert_eq!(member.footer, Some(member.actual));
+        }
+        let stored = &stats.members[0].blocks[0];
+        assert_eq!(stored.bits, 3 + 5 + 32 + 8 * input.len() as u64);
+        let symbols = &stats.members[2].blocks[0].symbols;
+        assert_eq!(
+            symbols[3],
+            Symbol::Match {
+                length_symbol: 263,
+                length: 9,
+                distance_symbol: 2,
+                distance: 3,
+            }
+        );
+        assert_eq!(symbols.last(), Some(&Symbol::EndOfBlock));
+
+        let mut report = vec![];
+        gzstat.write_report(&stats, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        for line in [
+            "    Modification time: 0x00000000 (Thu Jan  1 00:00:00 1970)",
+            "        LEN = 33 (0x0021), NLEN = 65502 (0xffde)",
+            "            Number of LL codes: 267 (HLIT = 10)",
+            "            10010001: 0x61 (a)",
+            "                0x107 is Length code 263",
+            "                0 extra length bits: Offset 0. Total length 9",
+            "                Distance code 00010: 2",
+            "            10101111: 0x7f (\x7f)",
+            "Read 3 gzip members",
+        ] {
+            assert!(report.lines().any(|l| l == line), "missing {line:?}");
+        }
+        assert_eq!(ctime(1_709_251_199, true), "Thu Feb 29 23:59:59 2024");
+
+        let stats = gzstat.analyze(&data[..data.len() - 3]);
+        assert!(matches!(stats.error, Some(InflateError::UnexpectedEof)));
+    }
+}
@@ -1,5 +1,6 @@
 #![allow(unused)]
 use std::{
+    cmp::Reverse,
     collections::{BinaryHeap, HashMap, LinkedList},
     fs::File,
     hash::Hash,
@@ -177,6 +178,107 @@ impl Codec {
     }
 }
 
+/// Compute Huffman code lengths from symbol frequencies, where no code is
+/// longer than `max_len`. Unused symbols (frequency 0) get length 0.
+pub fn code_lengths(freq: &[usize], max_len: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; freq.len()];
+    let symbols: Vec<usize> = (0..freq.len()).filter(|&s| freq[s] > 0).collect();
+    match symbols.len() {
+        0 => return lengths,
+        1 => {
+            lengths[symbols[0]] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    // build the tree bottom up, leaves are 0..symbols.len() and every
+    // internal node gets a larger index than its children
+    let mut parent = vec![0usize; symbols.len() * 2 - 1];
+    let mut heap = BinaryHeap::new();
+    for (node, &symbol) in symbols.iter().enumerate() {
+        heap.push(Reverse((freq[symbol], node)));
+    }
+    let mut next = symbols.len();
+    while heap.len() > 1 {
+        let Reverse((freq1, node1)) = heap.pop().unwrap();
+        let Reverse((freq2, node2)) = heap.pop().unwrap();
+        parent[node1] = next;
+        parent[node2] = next;
+        heap.push(Reverse((freq1 + freq2, next)));
+        next += 1;
+    }
+    let root = next - 1;
+    let mut depth = vec![0usize; next];
+    for node in (0..root).rev() {
+        depth[node] = depth[parent[node]] + 1;
+    }
+
+    // clamp the lengths, then repair the over-subscribed tree by moving
+    // codes down one level until the Kraft sum is exactly 1 again
+    let max_len = max_len as usize;
+    let mut bl_count = vec![0usize; max_len + 1];
+    for &d in &depth[..symbols.len()] {
+        bl_count[d.min(max_len)] += 1;
+    }
+    let mut total: usize = (1..=max_len)
+        .map(|len| bl_count[len] << (max_len - len))
+        .sum();
+    while total > 1 << max_len {
+        bl_count[max_len] -= 1;
+        for len in (1..max_len).rev() {
+            if bl_count[len] != 0 {
+                bl_count[len] -= 1;
+                bl_count[len + 1] += 2;
+                break;
+            }
+        }
+        total -= 1;
+    }
+
+    // the most frequent symbols get the shortest codes
+    let mut symbols = symbols;
+    symbols.sort_by_key(|&s| Reverse(freq[s]));
+    let mut symbols = symbols.into_iter();
+    for (len, &count) in bl_count.iter().enumerate().skip(1) {
+        for symbol in symbols.by_ref().take(count) {
+            lengths[symbol] = len as u8;
+        }
+    }
+    lengths
+}
+
+/// Assign canonical Huffman codes to symbols from their code lengths,
+/// refer to https://www.rfc-editor.org/rfc/rfc1951 S3.2.2
+///
+/// A length of 0 means the symbol is unused and gets an empty code.
+pub fn canonical_codes(lengths: &[u8]) -> Vec<Code> {
+    let max_len = lengths.iter().copied().max().unwrap_or(0) as usize;
+    let mut bl_count = vec![0u16; max_len + 1];
+    for &len in lengths {
+        if len > 0 {
+            bl_count[len as usize] += 1;
+        }
+    }
+    let mut next_code = vec![0u16; max_len + 1];
+    let mut code = 0u16;
+    for bits in 1..=max_len {
+        code = (code + bl_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return Code::new(Numeric::U16(0), 0);
+            }
+            let code = next_code[len as usize];
+            next_code[len as usize] += 1;
+            Code::new(Numeric::U16(code), len)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -195,6 +297,49 @@ mod tests {
         assert_eq!(res, "hello world");
     }
 
+    #[test]
+    fn test_code_lengths() {
+        assert_eq!(code_lengths(&[0, 0, 0], 15), vec![0, 0, 0]);
+        assert_eq!(code_lengths(&[0, 7, 0], 15), vec![0, 1, 0]);
+        assert_eq!(
+            code_lengths(&[5, 9, 12, 13, 16, 45], 15),
+            vec![4, 4, 3, 3, 3, 1]
+        );
+
+        // fibonacci frequencies make the deepest possible tree
+        let freq: Vec<usize> = (0..20)
+            .scan((1, 1), |(a, b), _| {
+                let f = *a;
+                (*a, *b) = (*b, *a + *b);
+                Some(f)
+            })
+            .collect();
+        let lengths = code_lengths(&freq, 7);
+        assert!(lengths.iter().all(|&len| (1..=7).contains(&len)));
+        let kraft: usize = lengths.iter().map(|&len| 1 << (7 - len)).sum();
+        assert_eq!(kraft, 1 << 7);
+    }
+
+    #[test]
+    fn test_canonical_codes() {
+        // the example in rfc1951 S3.2.2: ABCDEFGH with lengths (3, 3, 3, 3, 3, 2, 4, 4)
+        let codes = canonical_codes(&[3, 3, 3, 3, 3, 2, 4, 4]);
+        let codes: Vec<(usize, u8)> = codes.iter().map(|c| (c.value(), c.len())).collect();
+        assert_eq!(
+            codes,
+            vec![
+                (0b010, 3),
+                (0b011, 3),
+                (0b100, 3),
+                (0b101, 3),
+                (0b110, 3),
+                (0b00, 2),
+                (0b1110, 4),
+                (0b1111, 4)
+            ]
+        );
+    }
+
     // #[test]
     fn test_hlm() {
         let mut file = File::open("hlm.txt").unwrap();
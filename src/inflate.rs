@@ -0,0 +1,733 @@
+#![allow(unused)]
+
+use std::{
+    error, fmt,
+    io::{self, Read},
+};
+
+use crate::{
+    bit_io::BitReader,
+    deflate::{
+        fixed_distance_lengths, fixed_literal_lengths, CODE_LENGTH_ORDER, DISTANCE_BASE,
+        DISTANCE_EXTRA, LENGTH_BASE, LENGTH_EXTRA, WINDOW_SIZE,
+    },
+};
+
+const MAX_BITS: usize = 15;
+const MAX_LITERAL_CODES: usize = 286;
+const MAX_DISTANCE_CODES: usize = 30;
+const END_OF_BLOCK: u16 = 256;
+
+#[derive(Debug)]
+pub enum InflateError {
+    UnexpectedEof,
+    Io(io::Error),
+    InvalidHeader(&'static str),
+    HeaderCrcMismatch,
+    CrcMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    SizeMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    AdlerMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    /// the zlib stream was compressed with the preset dictionary of this Adler-32
+    DictionaryRequired(u32),
+    /// the preset dictionary given doesn't have the Adler-32 the stream names
+    DictionaryMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    InvalidBlockType,
+    /// NLEN of a stored block is not the one's complement of LEN
+    StoredLengthMismatch {
+        len: u16,
+        nlen: u16,
+    },
+    /// the code lengths in a dynamic block header don't describe usable codes
+    InvalidCodeLengths(&'static str),
+    OversubscribedTree,
+    IncompleteTree,
+    /// the bits read don't match any code of the tree
+    InvalidCode,
+    InvalidSymbol(u16),
+    /// a back-reference points before the start of the output
+    DistanceTooFar {
+        distance: usize,
+        available: usize,
+    },
+}
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InflateError::UnexpectedEof => write!(f, "unexpected end of deflate stream"),
+            InflateError::Io(err) => write!(f, "io error: {err}"),
+            InflateError::InvalidHeader(reason) => write!(f, "invalid header: {reason}"),
+            InflateError::HeaderCrcMismatch => write!(f, "header crc16 mismatch"),
+            InflateError::CrcMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "crc32 mismatch: expected {expected:#010x}, got {actual:#010x}"
+                )
+            }
+            InflateError::SizeMismatch { expected, actual } => {
+                write!(f, "size mismatch: expected {expected}, got {actual}")
+            }
+            InflateError::AdlerMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "adler32 mismatch: expected {expected:#010x}, got {actual:#010x}"
+                )
+            }
+            InflateError::DictionaryRequired(id) => {
+                write!(f, "preset dictionary {id:#010x} is required")
+            }
+            InflateError::DictionaryMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "preset dictionary mismatch: expected {expected:#010x}, got {actual:#010x}"
+                )
+            }
+            InflateError::InvalidBlockType => write!(f, "invalid block type 11"),
+            InflateError::StoredLengthMismatch { len, nlen } => {
+                write!(
+                    f,
+                    "stored block length {len:#06x} doesn't match NLEN {nlen:#06x}"
+                )
+            }
+            InflateError::InvalidCodeLengths(reason) => write!(f, "invalid code lengths: {reason}"),
+            InflateError::OversubscribedTree => write!(f, "over-subscribed huffman tree"),
+            InflateError::IncompleteTree => write!(f, "incomplete huffman tree"),
+            InflateError::InvalidCode => write!(f, "invalid huffman code"),
+            InflateError::InvalidSymbol(symbol) => write!(f, "invalid symbol {symbol}"),
+            InflateError::DistanceTooFar {
+                distance,
+                available,
+            } => write!(
+                f,
+                "distance {distance} is beyond the {available} bytes of history"
+            ),
+        }
+    }
+}
+
+impl error::Error for InflateError {}
+
+impl From<io::Error> for InflateError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            InflateError::UnexpectedEof
+        } else {
+            InflateError::Io(err)
+        }
+    }
+}
+
+impl From<InflateError> for io::Error {
+    fn from(err: InflateError) -> Self {
+        match err {
+            InflateError::Io(err) => err,
+            InflateError::UnexpectedEof => io::Error::new(io::ErrorKind::UnexpectedEof, err),
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// One symbol of a Huffman coded block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symbol {
+    Literal(u8),
+    EndOfBlock,
+    /// the symbols as coded along with the values they decode to
+    Match {
+        length_symbol: u16,
+        length: u16,
+        distance_symbol: u16,
+        distance: u16,
+    },
+}
+
+/// Decode the next symbol of a block along with the extra bits of a match
+pub(crate) fn decode_symbol<R: Read>(
+    reader: &mut BitReader<R>,
+    literal: &Huffman,
+    distance: &Huffman,
+) -> Result<Symbol, InflateError> {
+    let length_symbol = literal.decode(reader)?;
+    if length_symbol < END_OF_BLOCK {
+        return Ok(Symbol::Literal(length_symbol as u8));
+    }
+    if length_symbol == END_OF_BLOCK {
+        return Ok(Symbol::EndOfBlock);
+    }
+
+    let index = (length_symbol - 257) as usize;
+    if index >= LENGTH_BASE.len() {
+        return Err(InflateError::InvalidSymbol(length_symbol));
+    }
+    let length = LENGTH_BASE[index] + reader.read_bits(LENGTH_EXTRA[index])? as u16;
+
+    let distance_symbol = distance.decode(reader)?;
+    let index = distance_symbol as usize;
+    if index >= DISTANCE_BASE.len() {
+        return Err(InflateError::InvalidSymbol(distance_symbol));
+    }
+    let distance = DISTANCE_BASE[index] + reader.read_bits(DISTANCE_EXTRA[index])? as u16;
+    Ok(Symbol::Match {
+        length_symbol,
+        length,
+        distance_symbol,
+        distance,
+    })
+}
+
+/// Canonical huffman decoding table: the number of codes of each length and
+/// the symbols ordered by their code,
+/// refer to https://github.com/madler/zlib/blob/master/contrib/puff/puff.c
+pub(crate) struct Huffman {
+    count: [u16; MAX_BITS + 1],
+    symbol: Vec<u16>,
+    // number of codes left unused, 0 for a complete tree
+    left: i32,
+}
+
+impl Huffman {
+    pub(crate) fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+        let mut left = 1i32;
+        for &n in &count[1..] {
+            left <<= 1;
+            left -= n as i32;
+            if left < 0 {
+                return Err(InflateError::OversubscribedTree);
+            }
+        }
+
+        let mut offset = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offset[len + 1] = offset[len] + count[len];
+        }
+        let mut symbol = vec![0u16; lengths.len()];
+        for (s, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbol[offset[len as usize] as usize] = s as u16;
+                offset[len as usize] += 1;
+            }
+        }
+        Ok(Self {
+            count,
+            symbol,
+            left,
+        })
+    }
+
+    /// A single code of one bit is the only incomplete tree we accept,
+    /// besides one without any codes: the distance tree of a block that is
+    /// all literals may be empty, decoding from it fails with `InvalidCode`
+    pub(crate) fn check_complete(&self) -> Result<(), InflateError> {
+        let used: u16 = self.count[1..].iter().sum();
+        if self.left > 0 && used != 0 && !(used == 1 && self.count[1] == 1) {
+            return Err(InflateError::IncompleteTree);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn decode<R: Read>(&self, reader: &mut BitReader<R>) -> Result<u16, InflateError> {
+        // codes are packed starting from the most significant bit
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for len in 1..=MAX_BITS {
+            code |= reader.read_bit()? as i32;
+            let count = self.count[len] as i32;
+            if code - first < count {
+                return Ok(self.symbol[(index + code - first) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(InflateError::InvalidCode)
+    }
+}
+
+/// The code lengths at the start of a dynamic block,
+/// refer to https://www.rfc-editor.org/rfc/rfc1951 S3.2.7
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynamicHeader {
+    /// number of literal/length codes minus 257
+    pub hlit: u8,
+    /// number of distance codes minus 1
+    pub hdist: u8,
+    /// number of code length codes minus 4
+    pub hclen: u8,
+    /// code lengths of the code length alphabet, by symbol
+    pub code_length_lengths: [u8; 19],
+    /// the code length symbols as read, with the value of their extra
+    /// bits, like `run_length_encode` in deflate.rs produces them
+    pub code_length_symbols: Vec<(u8, u8)>,
+    /// literal/length code lengths followed by the distance ones
+    pub lengths: Vec<u8>,
+}
+
+impl DynamicHeader {
+    pub(crate) fn read<R: Read>(reader: &mut BitReader<R>) -> Result<Self, InflateError> {
+        let hlit = reader.read_bits(5)? as u8;
+        let hdist = reader.read_bits(5)? as u8;
+        let hclen = reader.read_bits(4)? as u8;
+        if hlit as usize + 257 > MAX_LITERAL_CODES || hdist as usize + 1 > MAX_DISTANCE_CODES {
+            return Err(InflateError::InvalidCodeLengths(
+                "too many length or distance codes",
+            ));
+        }
+
+        let mut code_length_lengths = [0u8; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..hclen as usize + 4] {
+            code_length_lengths[symbol] = reader.read_bits(3)? as u8;
+        }
+        let cl_code = Huffman::new(&code_length_lengths)?;
+        if cl_code.left > 0 {
+            return Err(InflateError::IncompleteTree);
+        }
+
+        let mut code_length_symbols = vec![];
+        let mut lengths = vec![0u8; hlit as usize + 257 + hdist as usize + 1];
+        let mut index = 0;
+        while index < lengths.len() {
+            let symbol = cl_code.decode(reader)?;
+            if symbol < 16 {
+                code_length_symbols.push((symbol as u8, 0));
+                lengths[index] = symbol as u8;
+                index += 1;
+                continue;
+            }
+            let extra = match symbol {
+                16 => reader.read_bits(2)?,
+                17 => reader.read_bits(3)?,
+                _ => reader.read_bits(7)?,
+            } as usize;
+            code_length_symbols.push((symbol as u8, extra as u8));
+            let (len, repeat) = match symbol {
+                16 => {
+                    if index == 0 {
+                        return Err(InflateError::InvalidCodeLengths(
+                            "repeat with no first length",
+                        ));
+                    }
+                    (lengths[index - 1], 3 + extra)
+                }
+                17 => (0, 3 + extra),
+                _ => (0, 11 + extra),
+            };
+            if index + repeat > lengths.len() {
+                return Err(InflateError::InvalidCodeLengths("too many lengths"));
+            }
+            lengths[index..index + repeat].fill(len);
+            index += repeat;
+        }
+        if lengths[END_OF_BLOCK as usize] == 0 {
+            return Err(InflateError::InvalidCodeLengths(
+                "missing end-of-block code",
+            ));
+        }
+        Ok(Self {
+            hlit,
+            hdist,
+            hclen,
+            code_length_lengths,
+            code_length_symbols,
+            lengths,
+        })
+    }
+
+    pub fn literal_lengths(&self) -> &[u8] {
+        &self.lengths[..self.hlit as usize + 257]
+    }
+
+    pub fn distance_lengths(&self) -> &[u8] {
+        &self.lengths[self.hlit as usize + 257..]
+    }
+}
+
+/// Decoder state for a raw DEFLATE stream, refer to https://www.rfc-editor.org/rfc/rfc1951
+///
+/// The compressed bits come from a `BitReader` passed to each call, so the
+/// caller can keep reading whatever follows the stream (e.g. a gzip footer).
+#[derive(Default)]
+pub struct Inflater {
+    output: Vec<u8>,
+    is_final: bool,
+    block: BlockState,
+}
+
+/// Where decoding stopped, so `inflate_until` can pick up mid-block
+#[derive(Default)]
+enum BlockState {
+    /// the next bits are a block header, or nothing after the final block
+    #[default]
+    Header,
+    Stored {
+        remaining: usize,
+    },
+    Codes {
+        literal: Huffman,
+        distance: Huffman,
+    },
+}
+
+impl Inflater {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start with the last `WINDOW_SIZE` bytes of a preset dictionary as
+    /// history, they form the beginning of `output()`
+    pub fn with_dictionary(dictionary: &[u8]) -> Self {
+        Self {
+            output: dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..].to_vec(),
+            ..Self::default()
+        }
+    }
+
+    /// Decode blocks until the final one
+    pub fn inflate<R: Read>(&mut self, reader: &mut BitReader<R>) -> Result<(), InflateError> {
+        self.inflate_until(reader, usize::MAX)
+    }
+
+    /// Decode until the output holds at least `limit` bytes or the final
+    /// block is done. A back reference may take the output a little past
+    /// `limit`.
+    pub fn inflate_until<R: Read>(
+        &mut self,
+        reader: &mut BitReader<R>,
+        limit: usize,
+    ) -> Result<(), InflateError> {
+        while self.output.len() < limit && !self.is_done() {
+            self.block = match std::mem::take(&mut self.block) {
+                BlockState::Header => self.read_block_header(reader)?,
+                BlockState::Stored { remaining } => {
+                    self.inflate_stored(reader, remaining, limit)?
+                }
+                BlockState::Codes { literal, distance } => {
+                    self.inflate_codes(reader, literal, distance, limit)?
+                }
+            };
+        }
+        Ok(())
+    }
+
+    /// Decode up to the end of the next block, which leaves the decoder at a
+    /// block boundary where all its state is the last 32K of output
+    pub fn inflate_block<R: Read>(
+        &mut self,
+        reader: &mut BitReader<R>,
+    ) -> Result<(), InflateError> {
+        loop {
+            self.block = match std::mem::take(&mut self.block) {
+                BlockState::Header => self.read_block_header(reader)?,
+                BlockState::Stored { remaining } => {
+                    self.inflate_stored(reader, remaining, usize::MAX)?
+                }
+                BlockState::Codes { literal, distance } => {
+                    self.inflate_codes(reader, literal, distance, usize::MAX)?
+                }
+            };
+            if matches!(self.block, BlockState::Header) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Whether the final block has been decoded completely
+    pub fn is_done(&self) -> bool {
+        self.is_final && matches!(self.block, BlockState::Header)
+    }
+
+    fn read_block_header<R: Read>(
+        &mut self,
+        reader: &mut BitReader<R>,
+    ) -> Result<BlockState, InflateError> {
+        self.is_final = reader.read_bit()?;
+        match reader.read_bits(2)? {
+            0 => {
+                let len = reader.read_u16_align_little_endian()?;
+                let nlen = reader.read_u16_align_little_endian()?;
+                if len != !nlen {
+                    return Err(InflateError::StoredLengthMismatch { len, nlen });
+                }
+                Ok(BlockState::Stored {
+                    remaining: len as usize,
+                })
+            }
+            1 => Ok(BlockState::Codes {
+                literal: Huffman::new(&fixed_literal_lengths())?,
+                distance: Huffman::new(&fixed_distance_lengths())?,
+            }),
+            2 => {
+                let (literal, distance) = self.read_dynamic_header(reader)?;
+                Ok(BlockState::Codes { literal, distance })
+            }
+            _ => Err(InflateError::InvalidBlockType),
+        }
+    }
+
+    fn inflate_stored<R: Read>(
+        &mut self,
+        reader: &mut BitReader<R>,
+        remaining: usize,
+        limit: usize,
+    ) -> Result<BlockState, InflateError> {
+        let n = remaining.min(limit - self.output.len());
+        for _ in 0..n {
+            let byte = reader.read_u8_align()?;
+            self.output.push(byte);
+        }
+        if n == remaining {
+            return Ok(BlockState::Header);
+        }
+        Ok(BlockState::Stored {
+            remaining: remaining - n,
+        })
+    }
+
+    fn read_dynamic_header<R: Read>(
+        &mut self,
+        reader: &mut BitReader<R>,
+    ) -> Result<(Huffman, Huffman), InflateError> {
+        let header = DynamicHeader::read(reader)?;
+        let literal = Huffman::new(header.literal_lengths())?;
+        literal.check_complete()?;
+        let distance = Huffman::new(header.distance_lengths())?;
+        distance.check_complete()?;
+        Ok((literal, distance))
+    }
+
+    fn inflate_codes<R: Read>(
+        &mut self,
+        reader: &mut BitReader<R>,
+        literal: Huffman,
+        distance: Huffman,
+        limit: usize,
+    ) -> Result<BlockState, InflateError> {
+        while self.output.len() < limit {
+            if !self.push_symbol(decode_symbol(reader, &literal, &distance)?)? {
+                return Ok(BlockState::Header);
+            }
+        }
+        Ok(BlockState::Codes { literal, distance })
+    }
+
+    /// Append the bytes `symbol` stands for, false at the end of the block
+    pub(crate) fn push_symbol(&mut self, symbol: Symbol) -> Result<bool, InflateError> {
+        match symbol {
+            Symbol::Literal(byte) => self.output.push(byte),
+            Symbol::EndOfBlock => return Ok(false),
+            Symbol::Match {
+                length, distance, ..
+            } => {
+                let (len, dist) = (length as usize, distance as usize);
+                if dist > self.output.len() || dist > WINDOW_SIZE {
+                    return Err(InflateError::DistanceTooFar {
+                        distance: dist,
+                        available: self.output.len().min(WINDOW_SIZE),
+                    });
+                }
+                // copy byte by byte, the source may overlap what we are writing
+                let start = self.output.len() - dist;
+                for i in 0..len {
+                    let byte = self.output[start + i];
+                    self.output.push(byte);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Drop all but the last `WINDOW_SIZE` bytes of output, the part back
+    /// references can still reach. Returns how many bytes were dropped.
+    pub fn trim_history(&mut self) -> usize {
+        let n = self.output.len().saturating_sub(WINDOW_SIZE);
+        self.output.drain(..n);
+        n
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn into_output(self) -> Vec<u8> {
+        self.output
+    }
+}
+
+/// Decode a raw DEFLATE stream
+pub fn inflate(input: &[u8]) -> Result<Vec<u8>, InflateError> {
+    let mut inflater = Inflater::new();
+    inflater.inflate(&mut BitReader::new(input))?;
+    Ok(inflater.into_output())
+}
+
+/// Decode a raw DEFLATE stream compressed with a preset dictionary
+pub fn inflate_with_dictionary(input: &[u8], dictionary: &[u8]) -> Result<Vec<u8>, InflateError> {
+    let mut inflater = Inflater::with_dictionary(dictionary);
+    inflater.inflate(&mut BitReader::new(input))?;
+    let history = dictionary.len().min(WINDOW_SIZE);
+    Ok(inflater.output()[history..].to_vec())
+}
+
+/// Decode the raw DEFLATE stream at the start of `input`, returns the data
+/// and how many bytes of `input` the stream took
+pub fn inflate_raw(input: &[u8]) -> Result<(Vec<u8>, usize), InflateError> {
+    let mut reader = BitReader::new(input);
+    let mut inflater = Inflater::new();
+    inflater.inflate(&mut reader)?;
+    let len = reader.bit_position().div_ceil(8) as usize;
+    Ok((inflater.into_output(), len))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::LinkedList;
+
+    use crate::{
+        bit_io::{BitIO, Code, Numeric},
+        deflate::{deflate_raw, deflate_raw_dictionary, BlockStrategy},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_inflate_stored() {
+        let input = [0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'];
+        assert_eq!(inflate(&input).unwrap(), b"abc");
+
+        let input = [0x01, 0x03, 0x00, 0x00, 0x00, b'a', b'b', b'c'];
+        assert!(matches!(
+            inflate(&input),
+            Err(InflateError::StoredLengthMismatch { len: 3, nlen: 0 })
+        ));
+        assert!(matches!(
+            inflate(&input[..4]),
+            Err(InflateError::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn test_inflate_raw() {
+        // zlib.compressobj(9, zlib.DEFLATED, -15) of b"hello hello hello" from python
+        let data = [0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00];
+        assert_eq!(inflate(&data).unwrap(), b"hello hello hello");
+
+        let input = b"permessage-deflate permessage-deflate";
+        for strategy in [
+            BlockStrategy::NoCompression,
+            BlockStrategy::FixedHuff,
+            BlockStrategy::DynamicHuff,
+            BlockStrategy::Adaptive,
+        ] {
+            let mut data = deflate_raw(input, strategy).as_vec();
+            let len = data.len();
+            data.extend(b"trailing bytes");
+            assert_eq!(inflate_raw(&data).unwrap(), (input.to_vec(), len));
+        }
+
+        let dictionary = b"permessage-deflate ";
+        let data = deflate_raw_dictionary(input, dictionary, 6).as_vec();
+        assert!(
+            data.len()
+                < deflate_raw(input, BlockStrategy::DynamicHuff)
+                    .as_vec()
+                    .len()
+        );
+        assert_eq!(inflate_with_dictionary(&data, dictionary).unwrap(), input);
+        assert!(inflate(&data).is_err());
+    }
+
+    #[test]
+    fn test_inflate_empty_distance_tree() {
+        // dynamic block coding 'A', end-of-block and length 3, no distance codes
+        let block = |symbols: &[(u8, u8)]| {
+            let mut bit_io = BitIO::new(LinkedList::new());
+            bit_io.write_code(&Code::new(Numeric::U8(0b101), 3));
+            bit_io.write_code(&Code::new(Numeric::U8(1), 5));
+            bit_io.write_code(&Code::new(Numeric::U8(0), 5));
+            bit_io.write_code(&Code::new(Numeric::U8(14), 4));
+            // code length code: 0 -> 00, 1 -> 01, 2 -> 10, 18 -> 11
+            for symbol in [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1] {
+                let len = if [0, 1, 2, 18].contains(&symbol) {
+                    2
+                } else {
+                    0
+                };
+                bit_io.write_code(&Code::new(Numeric::U8(len), 3));
+            }
+            let zeros = |bit_io: &mut BitIO, n: u8| {
+                bit_io.write_code_rev(&Code::new(Numeric::U8(0b11), 2));
+                bit_io.write_code(&Code::new(Numeric::U8(n - 11), 7));
+            };
+            zeros(&mut bit_io, 65);
+            bit_io.write_code_rev(&Code::new(Numeric::U8(0b01), 2));
+            zeros(&mut bit_io, 138);
+            zeros(&mut bit_io, 52);
+            bit_io.write_code_rev(&Code::new(Numeric::U8(0b10), 2));
+            bit_io.write_code_rev(&Code::new(Numeric::U8(0b10), 2));
+            bit_io.write_code_rev(&Code::new(Numeric::U8(0b00), 2));
+            // literal/length code: 'A' -> 0, end-of-block -> 10, 257 -> 11
+            for &(code, len) in symbols {
+                bit_io.write_code_rev(&Code::new(Numeric::U8(code), len));
+            }
+            let mut data = bit_io.as_vec();
+            data.extend([0; 4]);
+            data
+        };
+        let (a, end, length) = ((0b0, 1), (0b10, 2), (0b11, 2));
+        assert_eq!(inflate(&block(&[a, a, a, end])).unwrap(), b"AAA");
+        assert!(matches!(
+            inflate(&block(&[a, length])),
+            Err(InflateError::InvalidCode)
+        ));
+    }
+
+    #[test]
+    fn test_inflate_errors() {
+        // fixed block starting with <length 3, distance 1>
+        let mut bit_io = BitIO::new(LinkedList::new());
+        bit_io.write_code(&Code::new(Numeric::U8(0b011), 3));
+        bit_io.write_code_rev(&Code::new(Numeric::U8(0b0000001), 7));
+        bit_io.write_code_rev(&Code::new(Numeric::U8(0b00000), 5));
+        assert!(matches!(
+            inflate(&bit_io.as_vec()),
+            Err(InflateError::DistanceTooFar {
+                distance: 1,
+                available: 0
+            })
+        ));
+
+        // dynamic block whose code length code has four 1-bit codes
+        let mut bit_io = BitIO::new(LinkedList::new());
+        bit_io.write_code(&Code::new(Numeric::U8(0b101), 3));
+        bit_io.write_code(&Code::new(Numeric::U16(0), 14));
+        for _ in 0..4 {
+            bit_io.write_code(&Code::new(Numeric::U8(1), 3));
+        }
+        assert!(matches!(
+            inflate(&bit_io.as_vec()),
+            Err(InflateError::OversubscribedTree)
+        ));
+
+        assert!(matches!(
+            inflate(&[0x07]),
+            Err(InflateError::InvalidBlockType)
+        ));
+    }
+}
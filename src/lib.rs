@@ -1,9 +1,18 @@
 #[allow(non_snake_case)]
-mod LZ77;
+pub mod LZ77;
 mod adaptive_huffman;
 mod arithmetic_codec;
+pub mod bgzf;
 mod bit_io;
-mod deflate;
+pub mod deflate;
 mod graph_viz;
+pub mod gz_index;
+pub mod gzstat;
 mod huffman;
+pub mod inflate;
+mod optimal;
+pub mod stream;
+pub mod tar;
 mod utils;
+pub mod zip;
+pub mod zlib;
@@ -0,0 +1,226 @@
+#![allow(unused)]
+
+use crate::{
+    deflate::{
+        distance_symbol, dynamic_block_bits, fixed_distance_lengths, fixed_literal_lengths,
+        length_symbol, token_frequencies, MAX_MATCH, MIN_MATCH, WINDOW_SIZE,
+    },
+    huffman::code_lengths,
+    LZ77::{Codec, FinderKind, Value},
+};
+
+// most parse/re-estimate rounds, like the default of zopfli
+const MAX_ITERATIONS: usize = 15;
+// input parsed under one cost model at a time, zopfli's master block size
+const MASTER_BLOCK_SIZE: usize = 1_000_000;
+// how many nodes of its binary tree the match finder visits
+const MAX_CHAIN: usize = 4096;
+// what we charge for a symbol the current trees don't have a code for
+const UNUSED_SYMBOL_BITS: usize = 15;
+
+/// Bits each literal/length and distance symbol costs, extra bits included
+struct CostModel {
+    literal: Vec<usize>,
+    length: Vec<usize>,
+    distance: Vec<usize>,
+}
+
+impl CostModel {
+    fn new(literal_lengths: &[u8], distance_lengths: &[u8]) -> Self {
+        let bits = |len: u8| {
+            if len == 0 {
+                UNUSED_SYMBOL_BITS
+            } else {
+                len as usize
+            }
+        };
+        let length = (0..=MAX_MATCH)
+            .map(|len| {
+                if len < MIN_MATCH {
+                    return 0;
+                }
+                let (symbol, extra_len, _) = length_symbol(len);
+                bits(literal_lengths[symbol]) + extra_len as usize
+            })
+            .collect();
+        let distance = (0..30)
+            .map(|symbol| bits(distance_lengths[symbol]))
+            .collect();
+        Self {
+            literal: literal_lengths[..256]
+                .iter()
+                .map(|&len| bits(len))
+                .collect(),
+            length,
+            distance,
+        }
+    }
+
+    /// Costs of the fixed trees, a starting point before we know anything
+    fn fixed() -> Self {
+        Self::new(&fixed_literal_lengths(), &fixed_distance_lengths())
+    }
+
+    /// Costs under the dynamic trees built for `tokens`
+    fn from_tokens(tokens: &[Value]) -> Self {
+        let (literal_freq, distance_freq) = token_frequencies(tokens);
+        Self::new(
+            &code_lengths(&literal_freq, 15),
+            &code_lengths(&distance_freq, 15),
+        )
+    }
+
+    fn match_cost(&self, len: usize, distance: usize) -> usize {
+        let (symbol, extra_len, _) = distance_symbol(distance);
+        self.length[len] + self.distance[symbol] + extra_len as usize
+    }
+}
+
+/// Zopfli-style optimal parse of `input[start..]`, matches may refer back
+/// into `input[..start]`
+///
+/// The input is parsed in master blocks of `MASTER_BLOCK_SIZE` bytes, each
+/// getting its own cost model, while matches keep reaching back across
+/// block boundaries.
+pub(crate) fn optimal_parse(input: &[u8], start: usize) -> Vec<Value> {
+    optimal_parse_blocks(input, start, MASTER_BLOCK_SIZE)
+}
+
+fn optimal_parse_blocks(input: &[u8], start: usize, block_size: usize) -> Vec<Value> {
+    let codec = Codec::new(WINDOW_SIZE, MAX_MATCH, MIN_MATCH)
+        .max_chain(MAX_CHAIN)
+        .nice_length(MAX_MATCH)
+        .finder_kind(FinderKind::BinaryTree);
+    let mut finder = codec.finder(input);
+    let mut tokens = vec![];
+    for block_start in (start..input.len()).step_by(block_size) {
+        let block_end = (block_start + block_size).min(input.len());
+        let candidates: Vec<_> = (block_start..block_end)
+            .map(|pos| finder.candidates(pos))
+            .collect();
+        tokens.extend(parse_block(&input[block_start..block_end], &candidates));
+    }
+    tokens
+}
+
+/// Each round finds the cheapest literal/match path through `data` with a
+/// shortest path search under the bit costs of the trees the previous
+/// round's tokens would get. Rounds repeat until the size of the dynamic
+/// block stops shrinking, the best tokens seen are returned.
+fn parse_block(data: &[u8], candidates: &[Vec<(usize, usize)>]) -> Vec<Value> {
+    let mut model = CostModel::fixed();
+    let mut best: Option<(usize, Vec<Value>)> = None;
+    for _ in 0..MAX_ITERATIONS {
+        let tokens = shortest_path(data, candidates, &model);
+        let bits = dynamic_block_bits(&tokens);
+        if best
+            .as_ref()
+            .is_some_and(|(best_bits, _)| bits >= *best_bits)
+        {
+            break;
+        }
+        model = CostModel::from_tokens(&tokens);
+        best = Some((bits, tokens));
+    }
+    best.map_or_else(Vec::new, |(_, tokens)| tokens)
+}
+
+/// Cheapest tokens for `data` under `model`, `candidates[i]` holds the
+/// matches at `data[i]` as `Finder::candidates` returns them
+fn shortest_path(data: &[u8], candidates: &[Vec<(usize, usize)>], model: &CostModel) -> Vec<Value> {
+    // cost[i] is the cheapest way to encode data[..i], reached by step[i]
+    let mut cost = vec![usize::MAX; data.len() + 1];
+    let mut step = vec![Value::Literal(0); data.len() + 1];
+    cost[0] = 0;
+    for i in 0..data.len() {
+        let here = cost[i];
+        let literal = here + model.literal[data[i] as usize];
+        if literal < cost[i + 1] {
+            cost[i + 1] = literal;
+            step[i + 1] = Value::Literal(data[i]);
+        }
+        let mut len = MIN_MATCH;
+        for &(max_len, distance) in &candidates[i] {
+            // the match finder sees past the end of a master block
+            while len <= max_len.min(data.len() - i) {
+                let total = here + model.match_cost(len, distance);
+                if total < cost[i + len] {
+                    cost[i + len] = total;
+                    step[i + len] = Value::BackRef { len, distance };
+                }
+                len += 1;
+            }
+        }
+    }
+
+    let mut tokens = vec![];
+    let mut i = data.len();
+    while i > 0 {
+        let token = step[i];
+        i -= match token {
+            Value::Literal(_) => 1,
+            Value::BackRef { len, .. } => len,
+        };
+        tokens.push(token);
+    }
+    tokens.reverse();
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deflate::{BlockStrategy, GZip};
+
+    #[test]
+    fn test_optimal_parse() {
+        // greedy takes "abcd" at the second 'a' and is left with literals
+        // for "ef", matching "bcdef" from the next byte is cheaper
+        let input = b"xabcdyzbcdefabcdef";
+        let tokens = optimal_parse(input, 0);
+        assert_eq!(
+            tokens[tokens.len() - 2..],
+            [
+                Value::Literal(b'a'),
+                Value::BackRef {
+                    len: 5,
+                    distance: 6
+                }
+            ]
+        );
+
+        let input: Vec<u8> = (0..4000u32)
+            .map(|i| b"the quick brown fox jumps over the lazy dog "[(i * i % 89 % 44) as usize])
+            .collect();
+        let optimal = GZip::deflate_with(&input, BlockStrategy::Optimal).as_vec();
+        let level9 = GZip::deflate_level(&input, 9).as_vec();
+        assert!(optimal.len() <= level9.len());
+        assert_eq!(GZip::inflate(&optimal).unwrap(), input);
+
+        // master blocks get their own model, matches still cross into the previous one
+        let codec = Codec::new(WINDOW_SIZE, MAX_MATCH, MIN_MATCH);
+        let history = 500;
+        let tokens = optimal_parse_blocks(&input, history, 1000);
+        assert_eq!(
+            codec
+                .decode_with_dictionary(&input[..history], &tokens)
+                .unwrap(),
+            input[history..]
+        );
+        let mut pos = history;
+        let crossing = tokens.iter().any(|token| {
+            let len = match *token {
+                Value::Literal(_) => 1,
+                Value::BackRef { len, distance } => {
+                    if (pos - history) % 1000 < distance {
+                        return true;
+                    }
+                    len
+                }
+            };
+            pos += len;
+            false
+        });
+        assert!(crossing);
+    }
+}
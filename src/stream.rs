@@ -0,0 +1,309 @@
+#![allow(unused)]
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::{
+    bit_io::BitReader,
+    deflate::{
+        BlockStrategy, Deflater, FlushMode, GZipBuilder, GZipFooter, GZipHeader, WINDOW_SIZE,
+    },
+    inflate::Inflater,
+};
+
+/// Streaming gzip compressor, everything written to it comes out of `inner`
+/// as a single gzip member
+///
+/// Input is compressed in fixed-size chunks, so memory use does not grow
+/// with the input. The trailer is written by `finish`, or when the encoder
+/// is dropped, in which case errors are ignored.
+pub struct GzEncoder<W: Write> {
+    inner: Option<W>,
+    deflater: Deflater,
+    crc: crc32fast::Hasher,
+    input_size: u32,
+    finished: bool,
+}
+
+impl<W: Write> GzEncoder<W> {
+    /// Compress with the same defaults as `GZip::deflate`
+    pub fn new(inner: W) -> Self {
+        GZipBuilder::new().encoder(inner)
+    }
+
+    pub(crate) fn with_header(
+        inner: W,
+        header: &GZipHeader,
+        level: u8,
+        strategy: BlockStrategy,
+    ) -> Self {
+        let mut deflater = Deflater::new(level, strategy);
+        header.write(deflater.bit_io_mut());
+        Self {
+            inner: Some(inner),
+            deflater,
+            crc: crc32fast::Hasher::new(),
+            input_size: 0,
+            finished: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Write the last block and the trailer, then hand back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    /// Like `finish` but keeps the encoder, writing after it is an error
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        if !self.finished {
+            self.deflater.finish();
+            GZipFooter {
+                crc32: self.crc.clone().finalize(),
+                input_size: self.input_size,
+            }
+            .write(self.deflater.bit_io_mut());
+            self.finished = true;
+        }
+        self.dump()
+    }
+
+    /// Push out everything written so far without ending the member,
+    /// `Write::flush` does a sync flush
+    pub fn flush_with(&mut self, mode: FlushMode) -> io::Result<()> {
+        if !self.finished {
+            self.deflater.flush(mode);
+        }
+        self.dump()?;
+        self.inner.as_mut().unwrap().flush()
+    }
+
+    /// Pass the compressed bytes produced so far on to `inner`
+    fn dump(&mut self) -> io::Result<()> {
+        let output = self.deflater.take_output();
+        self.inner.as_mut().unwrap().write_all(&output)
+    }
+}
+
+impl<W: Write> Write for GzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Err(io::Error::other("write after finish"));
+        }
+        let n = self.deflater.write(buf);
+        self.crc.update(&buf[..n]);
+        self.input_size = self.input_size.wrapping_add(n as u32);
+        self.dump()?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_with(FlushMode::Sync)
+    }
+}
+
+impl<W: Write> Drop for GzEncoder<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// Streaming gzip decompressor, reads compressed data from `inner` only as
+/// the caller asks for output
+///
+/// Concatenated members are decoded one after another like `GZip::inflate`
+/// does. Besides the 32K history window at most one more window of output
+/// is held at a time. A CRC32 or ISIZE mismatch shows up as an
+/// `InvalidData` error once the end of the member is reached.
+pub struct GzDecoder<R: Read> {
+    reader: BitReader<BufReader<R>>,
+    header: GZipHeader,
+    inflater: Inflater,
+    // how much of the inflater output has been handed out
+    pos: usize,
+    crc: crc32fast::Hasher,
+    input_size: u32,
+}
+
+impl<R: Read> GzDecoder<R> {
+    /// Read the header of the first member
+    pub fn new(inner: R) -> io::Result<Self> {
+        let mut reader = BitReader::new(BufReader::new(inner));
+        let header = GZipHeader::read(&mut reader)?;
+        Ok(Self {
+            reader,
+            header,
+            inflater: Inflater::new(),
+            pos: 0,
+            crc: crc32fast::Hasher::new(),
+            input_size: 0,
+        })
+    }
+
+    /// Header of the member being decoded
+    pub fn header(&self) -> &GZipHeader {
+        &self.header
+    }
+
+    /// Decode the next piece of output, verifying the footer when the
+    /// member ends. Returns false at the end of the last member.
+    fn decode(&mut self) -> io::Result<bool> {
+        if self.inflater.is_done() {
+            if self.reader.is_at_end()? {
+                return Ok(false);
+            }
+            self.header = GZipHeader::read(&mut self.reader)?;
+            self.inflater = Inflater::new();
+            self.pos = 0;
+            self.crc = crc32fast::Hasher::new();
+            self.input_size = 0;
+        }
+
+        self.pos -= self.inflater.trim_history();
+        let start = self.inflater.output().len();
+        self.inflater
+            .inflate_until(&mut self.reader, start + WINDOW_SIZE)?;
+        let output = &self.inflater.output()[start..];
+        self.crc.update(output);
+        self.input_size = self.input_size.wrapping_add(output.len() as u32);
+
+        if self.inflater.is_done() {
+            GZipFooter::read(&mut self.reader)?.check(&GZipFooter {
+                crc32: self.crc.clone().finalize(),
+                input_size: self.input_size,
+            })?;
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for GzDecoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.inflater.output().len() {
+            if !self.decode()? {
+                break;
+            }
+        }
+        Ok(&self.inflater.output()[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deflate::GZip, inflate::inflate_raw};
+
+    #[test]
+    fn test_gz_encoder() {
+        // several chunks of input, written in odd-sized pieces
+        let input: Vec<u8> = (0..150_000u32)
+            .map(|i| b"streaming gzip encoder "[(i % 23) as usize] ^ (i / 4096) as u8)
+            .collect();
+        let mut encoder = GZipBuilder::new().level(1).encoder(Vec::new());
+        for piece in input.chunks(7777) {
+            encoder.write_all(piece).unwrap();
+        }
+        let output = encoder.finish().unwrap();
+        assert!(output.len() < input.len() / 10);
+        let member = GZip::inflate_member(&output).unwrap();
+        assert_eq!(member.data, input);
+        assert_eq!(member.header.extra_flag, 4);
+
+        // dropping the encoder writes the trailer too
+        let mut output = vec![];
+        {
+            let mut encoder = GzEncoder::new(&mut output);
+            encoder.write_all(b"hello hello hello").unwrap();
+        }
+        assert_eq!(GZip::inflate(&output).unwrap(), b"hello hello hello");
+
+        let output = GzEncoder::new(Vec::new()).finish().unwrap();
+        assert_eq!(GZip::inflate(&output).unwrap(), b"");
+
+        // the member is complete after try_finish, more input is refused
+        let mut encoder = GzEncoder::new(Vec::new());
+        encoder.write_all(b"done").unwrap();
+        encoder.try_finish().unwrap();
+        assert!(encoder.write_all(b" and more").is_err());
+        assert_eq!(GZip::inflate(&encoder.finish().unwrap()).unwrap(), b"done");
+    }
+
+    #[test]
+    fn test_gz_encoder_flush() {
+        let mut encoder = GzEncoder::new(Vec::new());
+        encoder.write_all(b"hello hello ").unwrap();
+        encoder.flush().unwrap();
+        assert!(encoder.get_ref().ends_with(&[0, 0, 0xff, 0xff]));
+
+        encoder.flush_with(FlushMode::Full).unwrap();
+        let restart = encoder.get_ref().len();
+        encoder.write_all(b"hello hello ").unwrap();
+        let output = encoder.finish().unwrap();
+        assert_eq!(GZip::inflate(&output).unwrap(), b"hello hello hello hello ");
+        // after a full flush the rest decodes without the earlier history
+        let (data, _) = inflate_raw(&output[restart..]).unwrap();
+        assert_eq!(data, b"hello hello ");
+    }
+
+    #[test]
+    fn test_gz_decoder() {
+        let input: Vec<u8> = (0..100_000u32)
+            .map(|i| (i % 251) as u8 ^ (i / 1000) as u8)
+            .collect();
+        let mut data = GZipBuilder::new()
+            .filename("a")
+            .unwrap()
+            .deflate(&input)
+            .as_vec();
+        data.extend(GZip::deflate_with(b"second member", BlockStrategy::NoCompression).as_vec());
+
+        let mut decoder = GzDecoder::new(&data[..]).unwrap();
+        assert_eq!(decoder.header().filename.as_deref(), Some(&b"a"[..]));
+        let mut output = vec![];
+        let mut buf = [0u8; 1000];
+        loop {
+            let n = decoder.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            output.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(output[..input.len()], input);
+        assert_eq!(&output[input.len()..], b"second member");
+        assert_eq!(decoder.header().filename, None);
+
+        let text = GZip::deflate(b"one\ntwo\n").as_vec();
+        let mut lines = GzDecoder::new(&text[..]).unwrap().lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "one");
+        assert_eq!(lines.next().unwrap().unwrap(), "two");
+        assert!(lines.next().is_none());
+
+        let len = data.len();
+        data[len - 1] ^= 1;
+        let err = io::copy(&mut GzDecoder::new(&data[..]).unwrap(), &mut io::sink()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
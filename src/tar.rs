@@ -0,0 +1,829 @@
+#![allow(unused)]
+
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::stream::{GzDecoder, GzEncoder};
+
+// refer to https://pubs.opengroup.org/onlinepubs/9699919799/utilities/pax.html
+const BLOCK_SIZE: usize = 512;
+// GNU tar pads archives to records of 20 blocks
+const RECORD_SIZE: usize = 20 * BLOCK_SIZE;
+const NAME_SIZE: usize = 100;
+const PREFIX_SIZE: usize = 155;
+const USTAR_MAGIC: &[u8; 8] = b"ustar\x0000";
+// the largest value an octal size/uid/gid field holds, beyond it pax records are needed
+const MAX_OCTAL_SIZE: u64 = 0o777_7777_7777;
+const MAX_OCTAL_ID: u64 = 0o777_7777;
+const PAX_HEADER_NAME: &str = "././@PaxHeader";
+
+const TYPE_FILE: u8 = b'0';
+const TYPE_OLD_FILE: u8 = 0;
+const TYPE_HARD_LINK: u8 = b'1';
+const TYPE_SYMLINK: u8 = b'2';
+const TYPE_DIRECTORY: u8 = b'5';
+const TYPE_CONTIGUOUS: u8 = b'7';
+const TYPE_PAX: u8 = b'x';
+const TYPE_PAX_GLOBAL: u8 = b'g';
+const TYPE_GNU_LONG_NAME: u8 = b'L';
+const TYPE_GNU_LONG_LINK: u8 = b'K';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    HardLink,
+    /// devices, FIFOs and the like, listed but not extracted
+    Other(u8),
+}
+
+impl EntryKind {
+    fn from_u8(typeflag: u8) -> Self {
+        match typeflag {
+            TYPE_FILE | TYPE_OLD_FILE | TYPE_CONTIGUOUS => EntryKind::File,
+            TYPE_HARD_LINK => EntryKind::HardLink,
+            TYPE_SYMLINK => EntryKind::Symlink,
+            TYPE_DIRECTORY => EntryKind::Directory,
+            other => EntryKind::Other(other),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            EntryKind::File => TYPE_FILE,
+            EntryKind::HardLink => TYPE_HARD_LINK,
+            EntryKind::Symlink => TYPE_SYMLINK,
+            EntryKind::Directory => TYPE_DIRECTORY,
+            EntryKind::Other(other) => other,
+        }
+    }
+
+    /// Whether `size` bytes of data follow the header
+    fn has_data(self) -> bool {
+        matches!(self, EntryKind::File | EntryKind::Other(_))
+    }
+}
+
+/// One tar entry, with whatever pax or GNU long name records came before
+/// it already applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TarHeader {
+    /// relative path with `/` separators, directories end with one
+    pub path: String,
+    pub kind: EntryKind,
+    pub mode: u32,
+    pub uid: u64,
+    pub gid: u64,
+    pub size: u64,
+    /// seconds since the unix epoch
+    pub mtime: u64,
+    /// target of a symlink or hard link
+    pub link_name: Option<String>,
+}
+
+impl TarHeader {
+    /// The ustar header block, preceded by a pax extended header when the
+    /// paths or numbers don't fit into its fields
+    fn to_blocks(&self) -> Vec<u8> {
+        let mut records = vec![];
+        let (prefix, name) = split_path(&self.path).unwrap_or_else(|| {
+            records.push(pax_record("path", &self.path));
+            ("", truncate(&self.path, NAME_SIZE))
+        });
+        let link_name = self.link_name.as_deref().unwrap_or("");
+        if link_name.len() > NAME_SIZE {
+            records.push(pax_record("linkpath", link_name));
+        }
+        for (key, value, max) in [
+            ("size", self.size, MAX_OCTAL_SIZE),
+            ("uid", self.uid, MAX_OCTAL_ID),
+            ("gid", self.gid, MAX_OCTAL_ID),
+            ("mtime", self.mtime, MAX_OCTAL_SIZE),
+        ] {
+            if value > max {
+                records.push(pax_record(key, &value.to_string()));
+            }
+        }
+
+        let mut blocks = vec![];
+        if !records.is_empty() {
+            let data = records.concat();
+            let pax = ustar_block(
+                "",
+                truncate(PAX_HEADER_NAME, NAME_SIZE),
+                TYPE_PAX,
+                0o644,
+                0,
+                0,
+                data.len() as u64,
+                self.mtime,
+                "",
+            );
+            blocks.extend_from_slice(&pax);
+            blocks.extend_from_slice(&data);
+            blocks.resize(blocks.len().next_multiple_of(BLOCK_SIZE), 0);
+        }
+        blocks.extend_from_slice(&ustar_block(
+            prefix,
+            name,
+            self.kind.to_u8(),
+            self.mode,
+            self.uid.min(MAX_OCTAL_ID),
+            self.gid.min(MAX_OCTAL_ID),
+            self.size.min(MAX_OCTAL_SIZE),
+            self.mtime,
+            truncate(link_name, NAME_SIZE),
+        ));
+        blocks
+    }
+
+    /// Parse a header block, returns None for the all-zero end of archive
+    /// block
+    fn parse(block: &[u8; BLOCK_SIZE]) -> io::Result<Option<(Self, u8)>> {
+        if block.iter().all(|&byte| byte == 0) {
+            return Ok(None);
+        }
+        let expected = parse_number(&block[148..156])?;
+        if checksum(block) != expected {
+            return Err(invalid_data("tar header checksum mismatch"));
+        }
+        let mut path = parse_string(&block[..NAME_SIZE]);
+        // GNU tar uses the prefix field for other things
+        if &block[257..265] == USTAR_MAGIC {
+            let prefix = parse_string(&block[345..345 + PREFIX_SIZE]);
+            if !prefix.is_empty() {
+                path = format!("{prefix}/{path}");
+            }
+        }
+        let typeflag = block[156];
+        let link_name = parse_string(&block[157..157 + NAME_SIZE]);
+        let header = TarHeader {
+            path,
+            kind: EntryKind::from_u8(typeflag),
+            mode: parse_number(&block[100..108])? as u32,
+            uid: parse_number(&block[108..116])?,
+            gid: parse_number(&block[116..124])?,
+            size: parse_number(&block[124..136])?,
+            mtime: parse_number(&block[136..148])?,
+            link_name: Some(link_name).filter(|link_name| !link_name.is_empty()),
+        };
+        Ok(Some((header, typeflag)))
+    }
+
+    /// Override fields with the records of a pax extended header
+    fn apply_pax(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut rest = data;
+        while !rest.is_empty() {
+            // "<length> <key>=<value>\n", the length counting everything
+            let space = rest
+                .iter()
+                .position(|&byte| byte == b' ')
+                .ok_or_else(|| invalid_data("bad pax record"))?;
+            let len: usize = std::str::from_utf8(&rest[..space])
+                .ok()
+                .and_then(|len| len.parse().ok())
+                .filter(|&len| len > space + 1 && len <= rest.len())
+                .ok_or_else(|| invalid_data("bad pax record length"))?;
+            let record = String::from_utf8_lossy(&rest[space + 1..len - 1]);
+            rest = &rest[len..];
+            let Some((key, value)) = record.split_once('=') else {
+                return Err(invalid_data("bad pax record"));
+            };
+            let number = || {
+                // times may have a fractional part
+                value
+                    .split('.')
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| invalid_data("bad pax number"))
+            };
+            match key {
+                "path" => self.path = value.to_string(),
+                "linkpath" => self.link_name = Some(value.to_string()),
+                "size" => self.size = number()?,
+                "uid" => self.uid = number()?,
+                "gid" => self.gid = number()?,
+                "mtime" => self.mtime = number()?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split `path` into the ustar prefix and name fields, if it fits
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= NAME_SIZE {
+        return Some(("", path));
+    }
+    // the separator between the two is implied
+    path.char_indices()
+        .filter(|&(i, c)| c == '/' && i <= PREFIX_SIZE && path.len() - i - 1 <= NAME_SIZE)
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(_, name)| !name.is_empty())
+}
+
+/// The longest prefix of `s` of at most `len` bytes on a char boundary
+fn truncate(s: &str, len: usize) -> &str {
+    let mut end = s.len().min(len);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    // the length includes its own digits
+    let base = key.len() + value.len() + 3;
+    let mut len = base + base.to_string().len();
+    if len.to_string().len() > base.to_string().len() {
+        len += 1;
+    }
+    format!("{len} {key}={value}\n").into_bytes()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn ustar_block(
+    prefix: &str,
+    name: &str,
+    typeflag: u8,
+    mode: u32,
+    uid: u64,
+    gid: u64,
+    size: u64,
+    mtime: u64,
+    link_name: &str,
+) -> [u8; BLOCK_SIZE] {
+    let mut block = [0u8; BLOCK_SIZE];
+    block[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut block[100..108], mode as u64 & 0o7777);
+    write_octal(&mut block[108..116], uid);
+    write_octal(&mut block[116..124], gid);
+    write_octal(&mut block[124..136], size);
+    write_octal(&mut block[136..148], mtime.min(MAX_OCTAL_SIZE));
+    block[156] = typeflag;
+    block[157..157 + link_name.len()].copy_from_slice(link_name.as_bytes());
+    block[257..265].copy_from_slice(USTAR_MAGIC);
+    block[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    // six octal digits, a NUL and a space
+    let checksum = format!("{:06o}\0 ", checksum(&block));
+    block[148..156].copy_from_slice(checksum.as_bytes());
+    block
+}
+
+/// Sum of the header bytes with the checksum field counted as spaces
+fn checksum(block: &[u8; BLOCK_SIZE]) -> u64 {
+    block
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| if (148..156).contains(&i) { b' ' } else { byte } as u64)
+        .sum()
+}
+
+/// Zero-padded octal followed by a NUL
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{value:0width$o}", width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+}
+
+/// Octal padded with spaces or NULs, or GNU base-256 when the high bit
+/// of the first byte is set
+fn parse_number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold((field[0] & 0x7f) as u64, |value, &byte| {
+                (value << 8) | byte as u64
+            }));
+    }
+    let digits = std::str::from_utf8(field)
+        .map_err(|_| invalid_data("bad tar number"))?
+        .trim_matches(|c| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid_data("bad tar number"))
+}
+
+fn parse_string(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+/// Write a tar archive, `finish` writes the end of archive blocks
+pub struct TarBuilder<W: Write> {
+    inner: W,
+    // bytes written to inner so far
+    offset: u64,
+}
+
+impl<W: Write> TarBuilder<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, offset: 0 }
+    }
+
+    /// Append an entry, `header.size` bytes of `data` are written for files
+    pub fn append<R: Read>(&mut self, header: &TarHeader, data: R) -> io::Result<()> {
+        self.write_raw(&header.to_blocks())?;
+        if header.kind.has_data() {
+            let copied = io::copy(&mut data.take(header.size), &mut self.inner)?;
+            if copied < header.size {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{} is shorter than its header says", header.path),
+                ));
+            }
+            self.offset += copied;
+            self.pad(BLOCK_SIZE)?;
+        }
+        Ok(())
+    }
+
+    /// Append the file, directory or symlink at `path` as `name`, symlinks
+    /// are stored as links rather than followed
+    pub fn append_path(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        let metadata = fs::symlink_metadata(path)?;
+        let file_type = metadata.file_type();
+        let (uid, gid) = owner(&metadata);
+        let mut header = TarHeader {
+            path: name.to_string(),
+            kind: EntryKind::File,
+            mode: mode(&metadata),
+            uid,
+            gid,
+            size: 0,
+            mtime: metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |mtime| mtime.as_secs()),
+            link_name: None,
+        };
+        if file_type.is_dir() {
+            header.kind = EntryKind::Directory;
+            if !header.path.ends_with('/') {
+                header.path.push('/');
+            }
+            self.append(&header, io::empty())
+        } else if file_type.is_symlink() {
+            header.kind = EntryKind::Symlink;
+            header.link_name = Some(utf8_path(&fs::read_link(path)?)?.to_string());
+            self.append(&header, io::empty())
+        } else if file_type.is_file() {
+            header.size = metadata.len();
+            self.append(&header, File::open(path)?)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} is not a file, directory or symlink", path.display()),
+            ))
+        }
+    }
+
+    /// Append everything below `dir`, with paths relative to it, in sorted
+    /// order so the same tree always gives the same archive
+    pub fn append_dir_all(&mut self, dir: &Path) -> io::Result<()> {
+        self.append_tree(dir, "")
+    }
+
+    fn append_tree(&mut self, dir: &Path, prefix: &str) -> io::Result<()> {
+        let mut children = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+        children.sort_by_key(|child| child.file_name());
+        for child in children {
+            let name = format!("{prefix}{}", utf8_path(Path::new(&child.file_name()))?);
+            let path = child.path();
+            self.append_path(&path, &name)?;
+            if child.file_type()?.is_dir() {
+                self.append_tree(&path, &format!("{name}/"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the two zero blocks that end the archive, then hand back the
+    /// writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_raw(&[0; 2 * BLOCK_SIZE])?;
+        self.pad(RECORD_SIZE)?;
+        Ok(self.inner)
+    }
+
+    fn pad(&mut self, size: usize) -> io::Result<()> {
+        let len = self.offset.next_multiple_of(size as u64) - self.offset;
+        self.write_raw(&vec![0; len as usize])
+    }
+
+    fn write_raw(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner.write_all(buf)?;
+        self.offset += buf.len() as u64;
+        Ok(())
+    }
+}
+
+fn utf8_path(path: &Path) -> io::Result<&str> {
+    path.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not valid UTF-8", path.display()),
+        )
+    })
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.is_dir() {
+        0o755
+    } else if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+#[cfg(unix)]
+fn owner(metadata: &fs::Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.uid() as u64, metadata.gid() as u64)
+}
+
+#[cfg(not(unix))]
+fn owner(metadata: &fs::Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+/// Permission bits only, extracted files never get setuid, setgid or sticky
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(unix)]
+fn symlink(target: &str, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(not(unix))]
+fn symlink(target: &str, path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks are only extracted on unix",
+    ))
+}
+
+/// Read a tar archive entry by entry, the data of the current entry is
+/// read through `Read`
+pub struct TarReader<R: Read> {
+    inner: R,
+    // data of the current entry not read yet, then the padding after it
+    remaining: u64,
+    padding: u64,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            remaining: 0,
+            padding: 0,
+        }
+    }
+
+    /// Header of the next entry, skipping what is left of the current one.
+    /// Returns None at the end of the archive.
+    pub fn next_entry(&mut self) -> io::Result<Option<TarHeader>> {
+        self.skip(self.remaining + self.padding)?;
+        self.remaining = 0;
+        self.padding = 0;
+        let mut pax = vec![];
+        let mut long_name = None;
+        let mut long_link = None;
+        loop {
+            let mut block = [0; BLOCK_SIZE];
+            // some writers leave out the end of archive blocks
+            match self.inner.read_exact(&mut block) {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                result => result?,
+            }
+            let Some((mut header, typeflag)) = TarHeader::parse(&block)? else {
+                return Ok(None);
+            };
+            if matches!(
+                typeflag,
+                TYPE_PAX | TYPE_PAX_GLOBAL | TYPE_GNU_LONG_NAME | TYPE_GNU_LONG_LINK
+            ) {
+                let mut data = vec![];
+                (&mut self.inner).take(header.size).read_to_end(&mut data)?;
+                if (data.len() as u64) < header.size {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                self.skip(header.size.next_multiple_of(BLOCK_SIZE as u64) - header.size)?;
+                let text = || parse_string(&data);
+                match typeflag {
+                    TYPE_PAX => pax = data,
+                    TYPE_GNU_LONG_NAME => long_name = Some(text()),
+                    TYPE_GNU_LONG_LINK => long_link = Some(text()),
+                    // global defaults, rarely used for anything we keep
+                    _ => {}
+                }
+                continue;
+            }
+
+            if let Some(long_name) = long_name {
+                header.path = long_name;
+            }
+            if long_link.is_some() {
+                header.link_name = long_link;
+            }
+            header.apply_pax(&pax)?;
+            if header.kind.has_data() {
+                self.remaining = header.size;
+                self.padding = header.size.next_multiple_of(BLOCK_SIZE as u64) - header.size;
+            }
+            return Ok(Some(header));
+        }
+    }
+
+    /// Extract every entry below `dest`
+    ///
+    /// Entries with absolute paths or `..` components are refused, as are
+    /// entries that would be written through a symlink extracted earlier.
+    /// Existing files are replaced. Directory modes and mtimes are set last
+    /// so read-only directories can still be filled.
+    pub fn unpack(mut self, dest: &Path) -> io::Result<()> {
+        fs::create_dir_all(dest)?;
+        let mut directories = vec![];
+        while let Some(header) = self.next_entry()? {
+            let path = entry_path(dest, &header.path)?;
+            if path == dest {
+                continue;
+            }
+            check_no_symlinks(dest, &path)?;
+            fs::create_dir_all(path.parent().unwrap())?;
+            if fs::symlink_metadata(&path).is_ok_and(|metadata| !metadata.is_dir()) {
+                fs::remove_file(&path)?;
+            }
+            let mtime = UNIX_EPOCH + Duration::from_secs(header.mtime);
+            match header.kind {
+                EntryKind::Directory => {
+                    fs::create_dir_all(&path)?;
+                    directories.push((path, header.mode, mtime));
+                }
+                EntryKind::File => {
+                    let mut file = File::create(&path)?;
+                    io::copy(&mut self, &mut file)?;
+                    file.set_modified(mtime)?;
+                    set_mode(&path, header.mode)?;
+                }
+                EntryKind::Symlink => {
+                    symlink(header.link_name.as_deref().unwrap_or(""), &path)?;
+                }
+                EntryKind::HardLink => {
+                    let target = entry_path(dest, header.link_name.as_deref().unwrap_or(""))?;
+                    check_no_symlinks(dest, &target)?;
+                    fs::hard_link(&target, &path)?;
+                }
+                EntryKind::Other(_) => {}
+            }
+        }
+        for (path, mode, mtime) in directories.into_iter().rev() {
+            File::open(&path)?.set_modified(mtime)?;
+            set_mode(&path, mode)?;
+        }
+        Ok(())
+    }
+
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.inner).take(len), &mut io::sink())?;
+        if skipped < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for TarReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf
+            .len()
+            .min(self.remaining.try_into().unwrap_or(usize::MAX));
+        if len == 0 {
+            return Ok(0);
+        }
+        let n = self.inner.read(&mut buf[..len])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// `name` as a path below `dest`, refusing anything that could leave it
+fn entry_path(dest: &Path, name: &str) -> io::Result<PathBuf> {
+    let mut path = dest.to_path_buf();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("refusing to extract {name}: it leaves the destination"),
+                ))
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Fail if any directory between `dest` and `path` is a symlink, an
+/// archive could otherwise plant one pointing anywhere and write through it
+fn check_no_symlinks(dest: &Path, path: &Path) -> io::Result<()> {
+    let mut current = dest.to_path_buf();
+    let relative = path.strip_prefix(dest).unwrap();
+    let mut parents = relative.components();
+    parents.next_back();
+    for component in parents {
+        current.push(component);
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("refusing to extract through symlink {}", current.display()),
+                ))
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Pack everything below `dir` into a .tar.gz written to `writer`
+pub fn pack_tgz<W: Write>(dir: &Path, writer: W) -> io::Result<W> {
+    let mut builder = TarBuilder::new(GzEncoder::new(writer));
+    builder.append_dir_all(dir)?;
+    builder.finish()?.finish()
+}
+
+/// Extract a .tar.gz below `dest`, see `TarReader::unpack`
+pub fn unpack_tgz<R: Read>(reader: R, dest: &Path) -> io::Result<()> {
+    TarReader::new(GzDecoder::new(reader)?).unpack(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tar-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_tgz() {
+        let root = temp_dir("pack");
+        let src = root.join("src");
+        let long_dir = "a-rather-long-directory-name/".repeat(5);
+        fs::create_dir_all(src.join(&long_dir)).unwrap();
+        fs::create_dir_all(src.join("empty")).unwrap();
+        fs::write(src.join("run.sh"), "#!/bin/sh\necho hi\n").unwrap();
+        set_mode(&src.join("run.sh"), 0o755).unwrap();
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7 % 251) as u8).collect();
+        fs::write(src.join(&long_dir).join("data.bin"), &data).unwrap();
+        fs::write(src.join("x".repeat(120)), "long name").unwrap();
+        symlink("run.sh", &src.join("link")).unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(src.join("run.sh"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let archive = pack_tgz(&src, vec![]).unwrap();
+        let mut names = vec![];
+        let mut reader = TarReader::new(GzDecoder::new(&archive[..]).unwrap());
+        while let Some(header) = reader.next_entry().unwrap() {
+            names.push(header.path);
+        }
+        assert_eq!(names.len(), 10);
+        assert!(names.contains(&format!("{long_dir}data.bin")));
+        assert!(names.contains(&"empty/".to_string()));
+
+        // values too big for the octal fields go into a pax header
+        let header = TarHeader {
+            path: "big".to_string(),
+            kind: EntryKind::File,
+            mode: 0o644,
+            uid: MAX_OCTAL_ID + 1,
+            gid: 0,
+            size: 0,
+            mtime: MAX_OCTAL_SIZE + 1,
+            link_name: None,
+        };
+        let mut builder = TarBuilder::new(vec![]);
+        builder.append(&header, io::empty()).unwrap();
+        let tar = builder.finish().unwrap();
+        let entry = TarReader::new(&tar[..]).next_entry().unwrap().unwrap();
+        assert_eq!(entry.uid, header.uid);
+        assert_eq!(entry.mtime, header.mtime);
+
+        let dest = root.join("dest");
+        unpack_tgz(&archive[..], &dest).unwrap();
+        assert_eq!(
+            fs::read(dest.join(&long_dir).join("data.bin")).unwrap(),
+            data
+        );
+        assert_eq!(fs::read(dest.join("x".repeat(120))).unwrap(), b"long name");
+        assert_eq!(
+            fs::read_link(dest.join("link")).unwrap(),
+            Path::new("run.sh")
+        );
+        assert!(dest.join("empty").is_dir());
+        let metadata = fs::metadata(dest.join("run.sh")).unwrap();
+        assert_eq!(mode(&metadata) & 0o777, 0o755);
+        assert_eq!(metadata.modified().unwrap(), mtime);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_unpack_traversal() {
+        let root = temp_dir("traversal");
+        let dest = root.join("dest");
+        let header = |path: &str, kind, link_name: Option<&str>| TarHeader {
+            path: path.to_string(),
+            kind,
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+            size: 4,
+            mtime: 0,
+            link_name: link_name.map(str::to_string),
+        };
+        let archive = |entries: &[TarHeader]| {
+            let mut builder = TarBuilder::new(vec![]);
+            for entry in entries {
+                builder.append(entry, &b"evil"[..]).unwrap();
+            }
+            builder.finish().unwrap()
+        };
+
+        for entries in [
+            vec![header("../outside", EntryKind::File, None)],
+            vec![header("/tmp/outside", EntryKind::File, None)],
+            vec![
+                header("link", EntryKind::Symlink, Some(root.to_str().unwrap())),
+                header("link/outside", EntryKind::File, None),
+            ],
+            vec![header("hard", EntryKind::HardLink, Some("../outside"))],
+        ] {
+            let err = TarReader::new(&archive(&entries)[..])
+                .unpack(&dest)
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(!root.join("outside").exists());
+        }
+
+        // a symlink as the last component is replaced, not written through
+        fs::write(root.join("target"), "keep").unwrap();
+        let entries = [
+            header("file", EntryKind::Symlink, Some("../target")),
+            header("file", EntryKind::File, None),
+        ];
+        TarReader::new(&archive(&entries)[..])
+            .unpack(&dest)
+            .unwrap();
+        assert_eq!(fs::read(root.join("target")).unwrap(), b"keep");
+        assert_eq!(fs::read(dest.join("file")).unwrap(), b"evil");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,754 @@
+#![allow(unused)]
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{
+    deflate::{deflate_raw_level, BlockStrategy, Deflater, DEFAULT_LEVEL, MAX_LEVEL},
+    inflate::{inflate_raw, InflateError},
+};
+
+// refer to https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+const LOCAL_HEADER_SIG: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIG: u32 = 0x0201_4b50;
+const DATA_DESCRIPTOR_SIG: u32 = 0x0807_4b50;
+const END_OF_CENTRAL_DIR_SIG: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIR_SIG: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIG: u32 = 0x0706_4b50;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const END_OF_CENTRAL_DIR_SIZE: usize = 22;
+const ZIP64_END_OF_CENTRAL_DIR_SIZE: usize = 56;
+const ZIP64_LOCATOR_SIZE: usize = 20;
+const MAX_COMMENT_SIZE: usize = 0xffff;
+const MAX_NAME_SIZE: usize = 0xffff;
+
+const FLAG_ENCRYPTED: u16 = 1;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+// version needed to extract: 2.0 for deflate, 4.5 for Zip64
+const VERSION_DEFLATE: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+// version made by, upper byte 3 for unix
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION_ZIP64;
+// 1980-01-01 00:00, the earliest MS-DOS timestamp, keeps archives reproducible
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+/// Sizes and offsets that don't fit into the 32-bit fields, or entry counts
+/// into the 16-bit ones, need Zip64 records
+const ZIP64_LIMIT: u64 = 0xffff_ffff;
+const ZIP64_ENTRIES_LIMIT: usize = 0xffff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
+    Stored,
+    Deflated,
+    Other(u16),
+}
+
+impl CompressionMethod {
+    fn from_u16(method: u16) -> Self {
+        match method {
+            METHOD_STORED => CompressionMethod::Stored,
+            METHOD_DEFLATED => CompressionMethod::Deflated,
+            other => CompressionMethod::Other(other),
+        }
+    }
+
+    fn to_u16(self) -> u16 {
+        match self {
+            CompressionMethod::Stored => METHOD_STORED,
+            CompressionMethod::Deflated => METHOD_DEFLATED,
+            CompressionMethod::Other(other) => other,
+        }
+    }
+}
+
+/// An entry as the central directory describes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    pub name: String,
+    pub method: CompressionMethod,
+    pub crc32: u32,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    /// file offset of the local header
+    pub header_offset: u64,
+    /// the sizes and CRC32 follow the data instead of being in the local header
+    pub data_descriptor: bool,
+    flags: u16,
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn get_u16(buf: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([buf[pos], buf[pos + 1]])
+}
+
+fn get_u32(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(buf[pos..pos + 4].try_into().unwrap())
+}
+
+fn get_u64(buf: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(buf[pos..pos + 8].try_into().unwrap())
+}
+
+/// The 32-bit field for `value`, all ones when the real value goes into
+/// the Zip64 extra field
+fn field_u32(value: u64, zip64: bool) -> u32 {
+    if zip64 {
+        ZIP64_LIMIT as u32
+    } else {
+        value as u32
+    }
+}
+
+fn invalid(reason: &'static str) -> io::Error {
+    InflateError::InvalidHeader(reason).into()
+}
+
+/// The headers store the name length in 16 bits
+fn check_name(name: &str) -> io::Result<()> {
+    if name.len() > MAX_NAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "entry name is longer than 65535 bytes",
+        ));
+    }
+    Ok(())
+}
+
+/// Read the `len` bytes a record claims there are, allocating only as much
+/// as the stream really has
+fn read_claimed<R: Read>(reader: &mut R, len: u64, what: &'static str) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    reader.take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(invalid(what));
+    }
+    Ok(buf)
+}
+
+/// Write a ZIP archive entry by entry, the central directory is written by
+/// `finish`, or when the writer is dropped, in which case errors are ignored
+///
+/// `add` takes a whole entry and writes its sizes into the local header.
+/// After `start_entry` the data is written through `Write` instead and
+/// compressed as it comes, its sizes and CRC32 follow it in a data
+/// descriptor. Zip64 records are used where the sizes need them.
+pub struct ZipWriter<W: Write> {
+    inner: Option<W>,
+    level: u8,
+    force_zip64: bool,
+    // bytes written to inner so far
+    offset: u64,
+    entries: Vec<ZipEntry>,
+    // the entry being written through `Write`, the last one of `entries`
+    streamed: Option<StreamedEntry>,
+}
+
+struct StreamedEntry {
+    deflater: Deflater,
+    crc: crc32fast::Hasher,
+    size: u64,
+    compressed_size: u64,
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner: Some(inner),
+            level: DEFAULT_LEVEL,
+            force_zip64: false,
+            offset: 0,
+            entries: vec![],
+            streamed: None,
+        }
+    }
+
+    /// Compression level of the entries, 0 stores them
+    pub fn level(mut self, level: u8) -> Self {
+        assert!(level <= MAX_LEVEL, "compression level must be 0 to 9");
+        self.level = level;
+        self
+    }
+
+    /// Always write Zip64 records, not only when the sizes need them
+    pub fn zip64(mut self, force: bool) -> Self {
+        self.force_zip64 = force;
+        self
+    }
+
+    /// Add a complete entry, stored when deflating doesn't make it smaller
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        check_name(name)?;
+        self.finish_entry()?;
+        let compressed = if self.level == 0 {
+            None
+        } else {
+            Some(deflate_raw_level(data, self.level).as_vec()).filter(|c| c.len() < data.len())
+        };
+        let (method, body) = match &compressed {
+            Some(compressed) => (CompressionMethod::Deflated, &compressed[..]),
+            None => (CompressionMethod::Stored, data),
+        };
+        let entry = ZipEntry {
+            name: name.to_string(),
+            method,
+            crc32: crc32fast::hash(data),
+            compressed_size: body.len() as u64,
+            uncompressed_size: data.len() as u64,
+            header_offset: self.offset,
+            data_descriptor: false,
+            flags: name_flags(name),
+        };
+        let zip64 = self.force_zip64
+            || entry.compressed_size >= ZIP64_LIMIT
+            || entry.uncompressed_size >= ZIP64_LIMIT;
+        let header = local_header(&entry, zip64);
+        self.write_raw(&header)?;
+        self.write_raw(body)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Start an entry whose data is then written through `Write`, it ends
+    /// with the next `add`, `start_entry` or `finish`
+    pub fn start_entry(&mut self, name: &str) -> io::Result<()> {
+        check_name(name)?;
+        self.finish_entry()?;
+        let entry = ZipEntry {
+            name: name.to_string(),
+            method: CompressionMethod::Deflated,
+            crc32: 0,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            header_offset: self.offset,
+            data_descriptor: true,
+            flags: name_flags(name) | FLAG_DATA_DESCRIPTOR,
+        };
+        // the sizes aren't known yet, so always leave room for 64-bit ones
+        let header = local_header(&entry, true);
+        self.write_raw(&header)?;
+        self.entries.push(entry);
+        self.streamed = Some(StreamedEntry {
+            deflater: Deflater::new(self.level, BlockStrategy::Adaptive),
+            crc: crc32fast::Hasher::new(),
+            size: 0,
+            compressed_size: 0,
+        });
+        Ok(())
+    }
+
+    /// Write the central directory, then hand back the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        self.finish_entry()?;
+        let start = self.offset;
+        let mut directory = vec![];
+        for entry in &self.entries {
+            central_header(entry, self.force_zip64, &mut directory);
+        }
+        self.write_raw(&directory)?;
+        let end = self.offset;
+
+        let size = end - start;
+        let count = self.entries.len();
+        let zip64 = self.force_zip64
+            || count >= ZIP64_ENTRIES_LIMIT
+            || size >= ZIP64_LIMIT
+            || start >= ZIP64_LIMIT;
+        let mut record = vec![];
+        if zip64 {
+            put_u32(&mut record, ZIP64_END_OF_CENTRAL_DIR_SIG);
+            // size of the rest of the record
+            put_u64(&mut record, ZIP64_END_OF_CENTRAL_DIR_SIZE as u64 - 12);
+            put_u16(&mut record, VERSION_MADE_BY);
+            put_u16(&mut record, VERSION_ZIP64);
+            // this disk and the disk the central directory starts on
+            put_u32(&mut record, 0);
+            put_u32(&mut record, 0);
+            put_u64(&mut record, count as u64);
+            put_u64(&mut record, count as u64);
+            put_u64(&mut record, size);
+            put_u64(&mut record, start);
+
+            put_u32(&mut record, ZIP64_LOCATOR_SIG);
+            put_u32(&mut record, 0);
+            put_u64(&mut record, end);
+            // total number of disks
+            put_u32(&mut record, 1);
+        }
+        let count = if zip64 {
+            ZIP64_ENTRIES_LIMIT as u16
+        } else {
+            count as u16
+        };
+        put_u32(&mut record, END_OF_CENTRAL_DIR_SIG);
+        put_u16(&mut record, 0);
+        put_u16(&mut record, 0);
+        put_u16(&mut record, count);
+        put_u16(&mut record, count);
+        put_u32(&mut record, field_u32(size, zip64));
+        put_u32(&mut record, field_u32(start, zip64));
+        // comment length
+        put_u16(&mut record, 0);
+        self.write_raw(&record)?;
+        self.inner.as_mut().unwrap().flush()
+    }
+
+    /// End the streamed entry, if any, with its data descriptor
+    fn finish_entry(&mut self) -> io::Result<()> {
+        let Some(mut streamed) = self.streamed.take() else {
+            return Ok(());
+        };
+        streamed.deflater.finish();
+        let output = streamed.deflater.into_output();
+        streamed.compressed_size += output.len() as u64;
+        self.write_raw(&output)?;
+
+        let entry = self.entries.last_mut().unwrap();
+        entry.crc32 = streamed.crc.finalize();
+        entry.compressed_size = streamed.compressed_size;
+        entry.uncompressed_size = streamed.size;
+        let mut descriptor = vec![];
+        put_u32(&mut descriptor, DATA_DESCRIPTOR_SIG);
+        put_u32(&mut descriptor, entry.crc32);
+        // 64-bit sizes, as announced by the Zip64 field of the local header
+        put_u64(&mut descriptor, entry.compressed_size);
+        put_u64(&mut descriptor, entry.uncompressed_size);
+        self.write_raw(&descriptor)
+    }
+
+    fn write_raw(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner.as_mut().unwrap().write_all(buf)?;
+        self.offset += buf.len() as u64;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ZipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let streamed = self
+            .streamed
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no entry started"))?;
+        let n = streamed.deflater.write(buf);
+        streamed.crc.update(&buf[..n]);
+        streamed.size += n as u64;
+        let output = streamed.deflater.take_output();
+        streamed.compressed_size += output.len() as u64;
+        self.write_raw(&output)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for ZipWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+fn name_flags(name: &str) -> u16 {
+    if name.is_ascii() {
+        0
+    } else {
+        FLAG_UTF8
+    }
+}
+
+/// Local file header, with both sizes in a Zip64 extra field if `zip64`
+fn local_header(entry: &ZipEntry, zip64: bool) -> Vec<u8> {
+    let mut header = vec![];
+    put_u32(&mut header, LOCAL_HEADER_SIG);
+    put_u16(
+        &mut header,
+        if zip64 {
+            VERSION_ZIP64
+        } else {
+            VERSION_DEFLATE
+        },
+    );
+    put_u16(&mut header, entry.flags);
+    put_u16(&mut header, entry.method.to_u16());
+    put_u16(&mut header, DOS_TIME);
+    put_u16(&mut header, DOS_DATE);
+    put_u32(&mut header, entry.crc32);
+    put_u32(&mut header, field_u32(entry.compressed_size, zip64));
+    put_u32(&mut header, field_u32(entry.uncompressed_size, zip64));
+    put_u16(&mut header, entry.name.len() as u16);
+    put_u16(&mut header, if zip64 { 20 } else { 0 });
+    header.extend_from_slice(entry.name.as_bytes());
+    if zip64 {
+        put_u16(&mut header, ZIP64_EXTRA_ID);
+        put_u16(&mut header, 16);
+        put_u64(&mut header, entry.uncompressed_size);
+        put_u64(&mut header, entry.compressed_size);
+    }
+    header
+}
+
+/// Central directory header, only the values that don't fit into their
+/// fields go into the Zip64 extra field unless `force_zip64`
+fn central_header(entry: &ZipEntry, force_zip64: bool, buf: &mut Vec<u8>) {
+    let uncompressed = force_zip64 || entry.uncompressed_size >= ZIP64_LIMIT;
+    let compressed = force_zip64 || entry.compressed_size >= ZIP64_LIMIT;
+    let offset = force_zip64 || entry.header_offset >= ZIP64_LIMIT;
+    let mut extra = vec![];
+    for (value, zip64) in [
+        (entry.uncompressed_size, uncompressed),
+        (entry.compressed_size, compressed),
+        (entry.header_offset, offset),
+    ] {
+        if zip64 {
+            put_u64(&mut extra, value);
+        }
+    }
+
+    put_u32(buf, CENTRAL_HEADER_SIG);
+    put_u16(buf, VERSION_MADE_BY);
+    put_u16(
+        buf,
+        if extra.is_empty() && !entry.data_descriptor {
+            VERSION_DEFLATE
+        } else {
+            VERSION_ZIP64
+        },
+    );
+    put_u16(buf, entry.flags);
+    put_u16(buf, entry.method.to_u16());
+    put_u16(buf, DOS_TIME);
+    put_u16(buf, DOS_DATE);
+    put_u32(buf, entry.crc32);
+    put_u32(buf, field_u32(entry.compressed_size, compressed));
+    put_u32(buf, field_u32(entry.uncompressed_size, uncompressed));
+    put_u16(buf, entry.name.len() as u16);
+    put_u16(
+        buf,
+        if extra.is_empty() {
+            0
+        } else {
+            4 + extra.len() as u16
+        },
+    );
+    // comment length, disk number start, internal attributes
+    put_u16(buf, 0);
+    put_u16(buf, 0);
+    put_u16(buf, 0);
+    // external attributes: a regular file with mode 0644
+    put_u32(buf, 0o100644 << 16);
+    put_u32(buf, field_u32(entry.header_offset, offset));
+    buf.extend_from_slice(entry.name.as_bytes());
+    if !extra.is_empty() {
+        put_u16(buf, ZIP64_EXTRA_ID);
+        put_u16(buf, extra.len() as u16);
+        buf.extend_from_slice(&extra);
+    }
+}
+
+/// Read a ZIP archive through its central directory, entries are only
+/// decompressed when asked for
+pub struct ZipArchive<R: Read + Seek> {
+    inner: R,
+    entries: Vec<ZipEntry>,
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    /// Find the end of central directory record, following it to the
+    /// Zip64 one if there is one, and read the central directory
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let file_len = inner.seek(SeekFrom::End(0))?;
+        let tail_len = file_len.min((END_OF_CENTRAL_DIR_SIZE + MAX_COMMENT_SIZE) as u64);
+        let mut tail = vec![0; tail_len as usize];
+        inner.seek(SeekFrom::Start(file_len - tail_len))?;
+        inner.read_exact(&mut tail)?;
+        // the record is followed by a comment of at most 64K, search
+        // backwards from the last place it could start
+        let pos = (0..tail.len().saturating_sub(END_OF_CENTRAL_DIR_SIZE - 1))
+            .rev()
+            .find(|&pos| get_u32(&tail, pos) == END_OF_CENTRAL_DIR_SIG)
+            .ok_or_else(|| invalid("end of central directory not found"))?;
+        let record = &tail[pos..pos + END_OF_CENTRAL_DIR_SIZE];
+        let mut count = get_u16(record, 10) as u64;
+        let mut size = get_u32(record, 12) as u64;
+        let mut start = get_u32(record, 16) as u64;
+
+        let record_offset = file_len - tail_len + pos as u64;
+        if record_offset >= ZIP64_LOCATOR_SIZE as u64 {
+            let mut locator = [0; ZIP64_LOCATOR_SIZE];
+            inner.seek(SeekFrom::Start(record_offset - ZIP64_LOCATOR_SIZE as u64))?;
+            inner.read_exact(&mut locator)?;
+            if get_u32(&locator, 0) == ZIP64_LOCATOR_SIG {
+                let mut record = [0; ZIP64_END_OF_CENTRAL_DIR_SIZE];
+                inner.seek(SeekFrom::Start(get_u64(&locator, 8)))?;
+                inner.read_exact(&mut record)?;
+                if get_u32(&record, 0) != ZIP64_END_OF_CENTRAL_DIR_SIG {
+                    return Err(invalid("bad Zip64 end of central directory signature"));
+                }
+                count = get_u64(&record, 32);
+                size = get_u64(&record, 40);
+                start = get_u64(&record, 48);
+            }
+        }
+
+        inner.seek(SeekFrom::Start(start))?;
+        let directory = read_claimed(&mut inner, size, "truncated central directory")?;
+        let mut entries = vec![];
+        let mut pos = 0;
+        for _ in 0..count {
+            let (entry, len) = parse_central_header(&directory[pos..])?;
+            entries.push(entry);
+            pos += len;
+        }
+        Ok(Self { inner, entries })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    /// Index of the entry called `name`
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.name == name)
+    }
+
+    /// Decompress entry `index`, checking its CRC32 and size
+    pub fn read_entry(&mut self, index: usize) -> io::Result<Vec<u8>> {
+        let entry = &self.entries[index];
+        if entry.flags & FLAG_ENCRYPTED != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "encrypted entries are not supported",
+            ));
+        }
+        let mut header = [0; LOCAL_HEADER_SIZE];
+        self.inner.seek(SeekFrom::Start(entry.header_offset))?;
+        self.inner.read_exact(&mut header)?;
+        if get_u32(&header, 0) != LOCAL_HEADER_SIG {
+            return Err(invalid("bad local header signature"));
+        }
+        // the local name and extra field may differ from the central ones,
+        // but the sizes in the central directory are always filled in
+        let skip = get_u16(&header, 26) as i64 + get_u16(&header, 28) as i64;
+        self.inner.seek(SeekFrom::Current(skip))?;
+        let body = read_claimed(
+            &mut self.inner,
+            entry.compressed_size,
+            "truncated entry data",
+        )?;
+
+        let data = match entry.method {
+            CompressionMethod::Stored => body,
+            CompressionMethod::Deflated => inflate_raw(&body)?.0,
+            CompressionMethod::Other(method) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("compression method {method} is not supported"),
+                ))
+            }
+        };
+        let crc32 = crc32fast::hash(&data);
+        if crc32 != entry.crc32 {
+            return Err(InflateError::CrcMismatch {
+                expected: entry.crc32,
+                actual: crc32,
+            }
+            .into());
+        }
+        if data.len() as u64 != entry.uncompressed_size {
+            // InflateError::SizeMismatch only holds gzip's 32-bit sizes
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "entry is {} bytes, the central directory says {}",
+                    data.len(),
+                    entry.uncompressed_size
+                ),
+            ));
+        }
+        Ok(data)
+    }
+
+    /// Decompress the entry called `name`
+    pub fn read_by_name(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        let index = self
+            .index_of(name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, name.to_string()))?;
+        self.read_entry(index)
+    }
+}
+
+/// Parse one central directory header, returns the entry and the size of
+/// the header
+fn parse_central_header(buf: &[u8]) -> io::Result<(ZipEntry, usize)> {
+    if buf.len() < CENTRAL_HEADER_SIZE || get_u32(buf, 0) != CENTRAL_HEADER_SIG {
+        return Err(invalid("bad central directory header"));
+    }
+    let name_len = get_u16(buf, 28) as usize;
+    let extra_len = get_u16(buf, 30) as usize;
+    let comment_len = get_u16(buf, 32) as usize;
+    let len = CENTRAL_HEADER_SIZE + name_len + extra_len + comment_len;
+    if buf.len() < len {
+        return Err(invalid("truncated central directory header"));
+    }
+    let flags = get_u16(buf, 8);
+    let name = &buf[CENTRAL_HEADER_SIZE..CENTRAL_HEADER_SIZE + name_len];
+    let mut entry = ZipEntry {
+        // names without the UTF-8 flag are officially CP437, in practice
+        // they are mostly ASCII or UTF-8 anyway
+        name: String::from_utf8_lossy(name).into_owned(),
+        method: CompressionMethod::from_u16(get_u16(buf, 10)),
+        crc32: get_u32(buf, 16),
+        compressed_size: get_u32(buf, 20) as u64,
+        uncompressed_size: get_u32(buf, 24) as u64,
+        header_offset: get_u32(buf, 42) as u64,
+        data_descriptor: flags & FLAG_DATA_DESCRIPTOR != 0,
+        flags,
+    };
+
+    let mut extra =
+        &buf[CENTRAL_HEADER_SIZE + name_len..CENTRAL_HEADER_SIZE + name_len + extra_len];
+    while extra.len() >= 4 {
+        let id = get_u16(extra, 0);
+        let size = (get_u16(extra, 2) as usize).min(extra.len() - 4);
+        if id == ZIP64_EXTRA_ID {
+            // present in this order, but only for the fields that are all ones
+            let mut values = extra[4..4 + size]
+                .chunks_exact(8)
+                .map(|value| get_u64(value, 0));
+            for field in [
+                &mut entry.uncompressed_size,
+                &mut entry.compressed_size,
+                &mut entry.header_offset,
+            ] {
+                if *field == ZIP64_LIMIT {
+                    *field = values
+                        .next()
+                        .ok_or_else(|| invalid("Zip64 extra field is too short"))?;
+                }
+            }
+        }
+        extra = &extra[4 + size..];
+    }
+    Ok((entry, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_zip() {
+        let text: Vec<u8> = (0..20000u32)
+            .map(|i| b"zip archive entry "[(i % 18) as usize] ^ (i / 3000) as u8)
+            .collect();
+        let mut writer = ZipWriter::new(Cursor::new(vec![])).level(1);
+        writer.add("text.txt", &text).unwrap();
+        writer.add("empty/", b"").unwrap();
+        writer.add("tiny", b"ab").unwrap();
+        writer.start_entry("streamed/ünïcode.txt").unwrap();
+        for piece in text.chunks(7000) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.add("after", b"after the streamed entry").unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        let mut archive = ZipArchive::new(Cursor::new(&data)).unwrap();
+        let names: Vec<_> = archive.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "text.txt",
+                "empty/",
+                "tiny",
+                "streamed/ünïcode.txt",
+                "after"
+            ]
+        );
+        assert_eq!(archive.entries()[0].method, CompressionMethod::Deflated);
+        assert!(archive.entries()[0].compressed_size < text.len() as u64 / 4);
+        assert_eq!(archive.entries()[2].method, CompressionMethod::Stored);
+        assert!(archive.entries()[3].data_descriptor);
+        assert_eq!(archive.read_by_name("text.txt").unwrap(), text);
+        assert_eq!(archive.read_entry(1).unwrap(), b"");
+        assert_eq!(archive.read_entry(2).unwrap(), b"ab");
+        assert_eq!(archive.read_entry(3).unwrap(), text);
+        assert_eq!(archive.read_entry(4).unwrap(), b"after the streamed entry");
+
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        let long_name = "n".repeat(65536);
+        for res in [writer.add(&long_name, b""), writer.start_entry(&long_name)] {
+            assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let mut corrupt = data.clone();
+        let offset = archive.entries()[2].header_offset as usize;
+        corrupt[offset + LOCAL_HEADER_SIZE + 4] ^= 1;
+        let mut archive = ZipArchive::new(Cursor::new(&corrupt)).unwrap();
+        assert_eq!(
+            archive.read_entry(2).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_zip64() {
+        let mut writer = ZipWriter::new(Cursor::new(vec![])).zip64(true);
+        writer.add("a", b"zip64 zip64 zip64 zip64").unwrap();
+        writer.start_entry("b").unwrap();
+        writer.write_all(b"streamed").unwrap();
+        let data = writer.finish().unwrap().into_inner();
+        let end = data.len() - END_OF_CENTRAL_DIR_SIZE;
+        assert_eq!(get_u32(&data, end - ZIP64_LOCATOR_SIZE), ZIP64_LOCATOR_SIG);
+        assert_eq!(get_u32(&data, end + 16), ZIP64_LIMIT as u32);
+
+        let mut archive = ZipArchive::new(Cursor::new(&data)).unwrap();
+        assert_eq!(archive.entries().len(), 2);
+        assert_eq!(archive.entries()[1].uncompressed_size, 8);
+        assert_eq!(archive.read_entry(0).unwrap(), b"zip64 zip64 zip64 zip64");
+        assert_eq!(archive.read_entry(1).unwrap(), b"streamed");
+
+        // sizes far beyond the end of the archive
+        let mut corrupt = data.clone();
+        let record = end - ZIP64_LOCATOR_SIZE - ZIP64_END_OF_CENTRAL_DIR_SIZE;
+        corrupt[record + 40..record + 48].copy_from_slice(&(1u64 << 62).to_le_bytes());
+        assert_eq!(
+            ZipArchive::new(Cursor::new(&corrupt)).err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+        let mut corrupt = data.clone();
+        let directory = get_u64(&data, record + 48) as usize;
+        corrupt[directory + 20..directory + 24].copy_from_slice(&0xfffffff0u32.to_le_bytes());
+        let mut archive = ZipArchive::new(Cursor::new(&corrupt)).unwrap();
+        assert_eq!(
+            archive.read_entry(0).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,311 @@
+#![allow(unused)]
+
+use std::{collections::LinkedList, io::Read};
+
+use crate::{
+    bit_io::{BitIO, BitReader},
+    deflate::{write_deflate, BlockStrategy, DEFAULT_LEVEL, MAX_LEVEL, WINDOW_SIZE},
+    inflate::{InflateError, Inflater},
+};
+
+const DEFLATE_METHOD: u8 = 8;
+// log2 of the window size minus 8, 7 for our 32K window
+const WINDOW_INFO: u8 = 7;
+const FLAG_DICT: u8 = 1 << 5;
+const ADLER_MOD: u32 = 65521;
+
+/// Rolling Adler-32 checksum, refer to https://www.rfc-editor.org/rfc/rfc1950 S8.2
+#[derive(Debug, Clone, Copy)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adler32 {
+    pub fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        // 5552 is the most bytes we can sum before b may overflow a u32
+        for chunk in data.chunks(5552) {
+            for &byte in chunk {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+            self.a %= ADLER_MOD;
+            self.b %= ADLER_MOD;
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut adler = Adler32::new();
+    adler.update(data);
+    adler.finish()
+}
+
+// refer to https://www.rfc-editor.org/rfc/rfc1950 S2.2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZLibHeader {
+    /// base-2 logarithm of the window size, 8 to 15
+    pub window_bits: u8,
+    /// FLEVEL, 0 (fastest) to 3 (maximum compression)
+    pub level: u8,
+    /// Adler-32 of the preset dictionary when FDICT is set
+    pub dictionary_id: Option<u32>,
+}
+
+impl ZLibHeader {
+    pub fn write(&self, bit_io: &mut BitIO) {
+        let cmf = ((self.window_bits - 8) << 4) | DEFLATE_METHOD;
+        let mut flag = self.level << 6;
+        if self.dictionary_id.is_some() {
+            flag |= FLAG_DICT;
+        }
+        // FCHECK makes CMF * 256 + FLG a multiple of 31
+        let remainder = (cmf as u16 * 256 + flag as u16) % 31;
+        flag |= ((31 - remainder) % 31) as u8;
+        bit_io.write_byte_align(cmf);
+        bit_io.write_byte_align(flag);
+        if let Some(id) = self.dictionary_id {
+            for byte in id.to_be_bytes() {
+                bit_io.write_byte_align(byte);
+            }
+        }
+    }
+
+    pub fn read<R: Read>(reader: &mut BitReader<R>) -> Result<Self, InflateError> {
+        let cmf = reader.read_u8_align()?;
+        let flag = reader.read_u8_align()?;
+        if cmf & 0x0f != DEFLATE_METHOD {
+            return Err(InflateError::InvalidHeader("unknown compression method"));
+        }
+        if cmf >> 4 > WINDOW_INFO {
+            return Err(InflateError::InvalidHeader("window size is too large"));
+        }
+        if !(cmf as u16 * 256 + flag as u16).is_multiple_of(31) {
+            return Err(InflateError::InvalidHeader("FCHECK mismatch"));
+        }
+        let dictionary_id = if flag & FLAG_DICT != 0 {
+            Some(read_u32_big_endian(reader)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            window_bits: (cmf >> 4) + 8,
+            level: flag >> 6,
+            dictionary_id,
+        })
+    }
+}
+
+fn read_u32_big_endian<R: Read>(reader: &mut BitReader<R>) -> Result<u32, InflateError> {
+    let mut res = 0u32;
+    for _ in 0..4 {
+        res = (res << 8) | reader.read_u8_align()? as u32;
+    }
+    Ok(res)
+}
+
+pub struct ZLib;
+
+impl ZLib {
+    pub fn deflate(input: &[u8]) -> BitIO {
+        Self::deflate_with(input, BlockStrategy::Adaptive)
+    }
+
+    pub fn deflate_with(input: &[u8], strategy: BlockStrategy) -> BitIO {
+        Self::compress(input, None, DEFAULT_LEVEL, strategy)
+    }
+
+    /// Compress with a level from 0 (store only) to 9 (slowest, smallest)
+    pub fn deflate_level(input: &[u8], level: u8) -> BitIO {
+        Self::compress(input, None, level, BlockStrategy::Adaptive)
+    }
+
+    /// Compress with the window primed with a preset dictionary, the header
+    /// carries FDICT and the dictionary's Adler-32 so the decoder can tell
+    /// which one it needs
+    pub fn deflate_with_dictionary(input: &[u8], dictionary: &[u8], level: u8) -> BitIO {
+        Self::compress(input, Some(dictionary), level, BlockStrategy::Adaptive)
+    }
+
+    fn compress(
+        input: &[u8],
+        dictionary: Option<&[u8]>,
+        level: u8,
+        strategy: BlockStrategy,
+    ) -> BitIO {
+        assert!(level <= MAX_LEVEL, "compression level must be 0 to 9");
+        // FLEVEL buckets the levels the way zlib does
+        let flevel = match level {
+            0 | 1 => 0,
+            2..=5 => 1,
+            6 => 2,
+            _ => 3,
+        };
+        let mut bit_io = BitIO::new(LinkedList::new());
+        ZLibHeader {
+            window_bits: WINDOW_INFO + 8,
+            level: flevel,
+            dictionary_id: dictionary.map(adler32),
+        }
+        .write(&mut bit_io);
+        write_deflate(
+            input,
+            dictionary.unwrap_or_default(),
+            level,
+            strategy,
+            &mut bit_io,
+        );
+        for byte in adler32(input).to_be_bytes() {
+            bit_io.write_byte_align(byte);
+        }
+        bit_io
+    }
+
+    /// Decompress a zlib stream, checking its Adler-32
+    pub fn inflate(input: &[u8]) -> Result<Vec<u8>, InflateError> {
+        Self::decompress(input, None)
+    }
+
+    /// Decompress a zlib stream that may need a preset dictionary, the
+    /// dictionary is ignored when the header doesn't ask for one
+    pub fn inflate_with_dictionary(
+        input: &[u8],
+        dictionary: &[u8],
+    ) -> Result<Vec<u8>, InflateError> {
+        Self::decompress(input, Some(dictionary))
+    }
+
+    fn decompress(input: &[u8], dictionary: Option<&[u8]>) -> Result<Vec<u8>, InflateError> {
+        let mut reader = BitReader::new(input);
+        let header = ZLibHeader::read(&mut reader)?;
+        let dictionary = match (header.dictionary_id, dictionary) {
+            (None, _) => &[][..],
+            (Some(id), None) => return Err(InflateError::DictionaryRequired(id)),
+            (Some(expected), Some(dictionary)) => {
+                let actual = adler32(dictionary);
+                if expected != actual {
+                    return Err(InflateError::DictionaryMismatch { expected, actual });
+                }
+                dictionary
+            }
+        };
+        let mut inflater = Inflater::with_dictionary(dictionary);
+        inflater.inflate(&mut reader)?;
+        let history = dictionary.len().min(WINDOW_SIZE);
+        let data = inflater.output()[history..].to_vec();
+
+        let expected = read_u32_big_endian(&mut reader)?;
+        let actual = adler32(&data);
+        if expected != actual {
+            return Err(InflateError::AdlerMismatch { expected, actual });
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+
+        let input = vec![0xffu8; 100000];
+        let mut adler = Adler32::new();
+        for chunk in input.chunks(777) {
+            adler.update(chunk);
+        }
+        assert_eq!(adler.finish(), adler32(&input));
+    }
+
+    #[test]
+    fn test_zlib() {
+        // zlib.compress(b"hello") and zlib.compress(b"hello", 1) from python
+        let data = [
+            0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00, 0x06, 0x2c, 0x02, 0x15,
+        ];
+        assert_eq!(ZLib::inflate(&data).unwrap(), b"hello");
+        let mut data = data;
+        data[1] = 0x01;
+        assert_eq!(ZLib::inflate(&data).unwrap(), b"hello");
+
+        let input = b"PNG IDAT, PDF streams and git objects all use zlib. ".repeat(10);
+        for strategy in [
+            BlockStrategy::NoCompression,
+            BlockStrategy::FixedHuff,
+            BlockStrategy::DynamicHuff,
+            BlockStrategy::Adaptive,
+        ] {
+            let data = ZLib::deflate_with(&input, strategy).as_vec();
+            assert_eq!((data[0] as u16 * 256 + data[1] as u16) % 31, 0);
+            assert_eq!(ZLib::inflate(&data).unwrap(), input);
+        }
+
+        for level in 0..=MAX_LEVEL {
+            let data = ZLib::deflate_level(&input, level).as_vec();
+            assert_eq!(ZLib::inflate(&data).unwrap(), input);
+        }
+        assert_eq!(ZLib::deflate_level(&input, 9).as_vec()[1] >> 6, 3);
+
+        let mut data = ZLib::deflate(&input).as_vec();
+        let len = data.len();
+        data[len - 1] ^= 1;
+        assert!(matches!(
+            ZLib::inflate(&data),
+            Err(InflateError::AdlerMismatch { .. })
+        ));
+        data[1] ^= 1;
+        assert!(matches!(
+            ZLib::inflate(&data),
+            Err(InflateError::InvalidHeader("FCHECK mismatch"))
+        ));
+    }
+
+    #[test]
+    fn test_zlib_dictionary() {
+        // zlib.compressobj(zdict=dictionary) of b'{"name": "x", "id": 1}' from python
+        let dictionary = br#"{"name": "", "id": }"#;
+        let data = [
+            0x78, 0xbb, 0x3a, 0x0d, 0x05, 0x33, 0xab, 0x86, 0x8b, 0x55, 0xc0, 0x05, 0x0d, 0x6b,
+            0x01, 0x47, 0xa2, 0x05, 0xdc,
+        ];
+        assert!(matches!(
+            ZLib::inflate(&data),
+            Err(InflateError::DictionaryRequired(0x3a0d0533))
+        ));
+        assert!(matches!(
+            ZLib::inflate_with_dictionary(&data, b"{}"),
+            Err(InflateError::DictionaryMismatch { .. })
+        ));
+        assert_eq!(
+            ZLib::inflate_with_dictionary(&data, dictionary).unwrap(),
+            br#"{"name": "x", "id": 1}"#
+        );
+
+        let input = br#"{"name": "compression_rs", "id": 42}"#;
+        let data = ZLib::deflate_with_dictionary(input, dictionary, 9).as_vec();
+        assert_eq!(data[1] & FLAG_DICT, FLAG_DICT);
+        assert_eq!(data[2..6], adler32(dictionary).to_be_bytes());
+        assert!(data.len() < ZLib::deflate_level(input, 9).as_vec().len());
+        assert_eq!(
+            ZLib::inflate_with_dictionary(&data, dictionary).unwrap(),
+            input
+        );
+    }
+}
@@ -2,4 +2,4 @@
 
 cargo test test_deflate_no_compression
 
-python gzstat.py < no_compression.gz
\ No newline at end of file
+cargo run --quiet --bin gzstat < no_compression.gz